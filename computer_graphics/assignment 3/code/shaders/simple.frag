@@ -2,13 +2,47 @@
 
 in layout(location = 0) vec4 vertexColor;
 in layout(location = 1) vec3 vertexNormal;
+in layout(location = 2) vec3 vertexEmissive;
+in layout(location = 3) vec3 vertexPosition;
 
 out vec4 color;
 
+// The lights of the scene in world space, see light.rs
+#define MAX_LIGHTS 8
+
+struct Light {
+    int kind;         // 0 directional, 1 point, 2 spot
+    vec3 position;
+    vec3 direction;
+    vec3 color;       // Already multiplied by the intensity
+    float range;      // Point and spot lights fade out to nothing this far away
+    float cos_inner;  // Spot lights are at full strength inside this cone
+    float cos_outer;  // and fade out towards this one
+};
+
+uniform Light lights[MAX_LIGHTS];
+uniform int light_count;
+
 void main()
 {   
-    // Implement the Lambertian shading model
-    vec3 lightDirection = normalize(vec3(0.8, -0.5, 0.6));    
-    vec4 finalColor = vertexColor * max(0, dot(vertexNormal, -lightDirection));
-    color = vec4(finalColor.rgb, vertexColor.a);
+    // Implement the Lambertian shading model, summed over the lights
+    vec3 normal = normalize(vertexNormal);
+    vec3 light = vec3(0.0);
+    for (int i = 0; i < light_count; i++) {
+        vec3 toLight = -lights[i].direction;
+        float attenuation = 1.0;
+        if (lights[i].kind != 0) {
+            vec3 offset = lights[i].position - vertexPosition;
+            float distance = length(offset);
+            toLight = offset / max(distance, 1e-6);
+            attenuation = pow(clamp(1.0 - distance / lights[i].range, 0.0, 1.0), 2.0);
+        }
+        if (lights[i].kind == 2) {
+            float cosAngle = dot(-toLight, lights[i].direction);
+            attenuation *= smoothstep(lights[i].cos_outer, lights[i].cos_inner, cosAngle);
+        }
+        light += lights[i].color * attenuation * max(0, dot(normal, toLight));
+    }
+    vec3 finalColor = vertexColor.rgb * light;
+    color = vec4(finalColor + vertexEmissive, vertexColor.a);
 }
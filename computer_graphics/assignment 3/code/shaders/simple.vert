@@ -3,22 +3,29 @@
 in layout(location=0) vec3 position;
 in layout(location=1) vec4 color;
 in layout(location=2) vec3 normal;
-uniform layout(location=0) mat4 mvp_matrix;
-uniform layout(location=1) mat4 model_matrix;
+in layout(location=3) mat4 model_matrix; // Per instance, takes locations 3 to 6
+in layout(location=7) vec4 tint;         // Per instance
+in layout(location=8) vec3 emissive;     // Per instance
+uniform layout(location=0) mat4 view_projection_matrix;
 
 out layout(location=0) vec4 vertexColor;
 out layout(location=1) vec3 vertexNormal;
+out layout(location=2) vec3 vertexEmissive;
+out layout(location=3) vec3 vertexPosition; // In world space, for the lights
 
 void main()
 {
     // Color vector to pass to the fragment shader
-    vertexColor = color;
+    vertexColor = color * tint;
+    vertexEmissive = emissive;
 
     // Normal vector to pass to the fragment shader
     // vertexNormal = normal;
     vertexNormal = normalize(mat3(model_matrix) * normal);;
 
     // Transformed vertex
-    gl_Position =  mvp_matrix * vec4(position, 1.0);
+    vec4 worldPosition = model_matrix * vec4(position, 1.0);
+    vertexPosition = worldPosition.xyz;
+    gl_Position =  view_projection_matrix * worldPosition;
 
 }
@@ -0,0 +1,159 @@
+extern crate nalgebra_glm as glm;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use crate::geometry::{Ray, TriangleBvh};
+use crate::mesh::Mesh;
+
+// Baked ambient occlusion: for every vertex, shoot rays into the hemisphere around its normal and
+// count how many of them hit the mesh itself within `max_distance`. The result is the fraction of
+// rays that escaped (1 = fully open, 0 = fully enclosed), which can be kept as its own attribute or
+// multiplied into the vertex colors with `darken_vertex_colors`.
+//
+// Every vertex draws its rays from its own generator seeded from `seed` and the vertex index, so
+// the bake is deterministic for a given seed no matter how the work is split between threads.
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct AmbientOcclusion {
+    pub samples      : usize,  // Rays per vertex
+    pub max_distance : f32,    // Geometry further away than this does not occlude
+    pub seed         : u64,
+}
+
+impl AmbientOcclusion {
+    pub fn bake(&self, mesh: &Mesh) -> Vec<f32> {
+        let vertex_count = mesh.vertex_count();
+        if vertex_count == 0 || self.samples == 0 {
+            return vec![1.0; vertex_count];
+        }
+
+        let bvh = TriangleBvh::from_mesh(mesh);
+        let normals = mesh.vertex_normals();
+        // Seam duplicates share a position, bake them once so they also share the result
+        let welded = mesh.welded_vertices();
+        // Start the rays slightly above the surface so they don't hit the triangles they leave from
+        let bias = glm::length(&bvh.bounds().extent()) * 1e-5;
+
+        let mut accessibility = vec![1.0f32; vertex_count];
+        let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let chunk_size = vertex_count.div_ceil(threads);
+        std::thread::scope(|scope| {
+            for (chunk, values) in accessibility.chunks_mut(chunk_size).enumerate() {
+                let (bvh, normals, welded) = (&bvh, &normals, &welded);
+                scope.spawn(move || {
+                    for (offset, value) in values.iter_mut().enumerate() {
+                        let vertex = chunk * chunk_size + offset;
+                        if welded[vertex] as usize == vertex {
+                            *value = self.bake_vertex(mesh, bvh, vertex, &normals[vertex], bias);
+                        }
+                    }
+                });
+            }
+        });
+
+        // Copy the result over to the seam duplicates, which always come after their original
+        for vertex in 0..vertex_count {
+            accessibility[vertex] = accessibility[welded[vertex] as usize];
+        }
+        accessibility
+    }
+
+    // Bakes into the mesh's vertex colors, and notes the bake on the mesh's source so a copy loaded
+    // from it again (e.g. from a saved scene) gets the same colors
+    pub fn bake_into_colors(&self, mesh: &mut Mesh) {
+        let accessibility = self.bake(mesh);
+        darken_vertex_colors(mesh, &accessibility);
+        if let Some(source) = &mut mesh.source {
+            source.occlusion = Some(*self);
+        }
+    }
+
+    fn bake_vertex(&self, mesh: &Mesh, bvh: &TriangleBvh, vertex: usize, normal: &glm::Vec3, bias: f32) -> f32 {
+        if glm::length(normal) == 0.0 {
+            return 1.0;
+        }
+        let normal = &glm::normalize(normal);
+        let mut rng = StdRng::seed_from_u64(self.seed ^ (vertex as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        let origin = mesh.position(vertex as u32) + normal * bias;
+
+        let mut escaped = 0;
+        for _ in 0..self.samples {
+            // Cosine weighted direction around the normal
+            let phi = 2.0 * PI * rng.gen::<f32>();
+            let r2: f32 = rng.gen();
+            let r = r2.sqrt();
+            let direction = tangent * (r * phi.cos()) + bitangent * (r * phi.sin()) + normal * (1.0 - r2).sqrt();
+            if !bvh.any_hit(&Ray::new(origin, direction), self.max_distance) {
+                escaped += 1;
+            }
+        }
+        escaped as f32 / self.samples as f32
+    }
+}
+
+// Multiply the RGB part of the vertex colors with the baked accessibility, leaving alpha alone
+pub fn darken_vertex_colors(mesh: &mut Mesh, accessibility: &[f32]) {
+    for (color, &value) in mesh.colors.chunks_exact_mut(4).zip(accessibility) {
+        color[0] *= value;
+        color[1] *= value;
+        color[2] *= value;
+    }
+}
+
+// internal helper
+fn orthonormal_basis(normal: &glm::Vec3) -> (glm::Vec3, glm::Vec3) {
+    let helper = if normal.x.abs() > 0.9 { glm::vec3(0.0, 1.0, 0.0) } else { glm::vec3(1.0, 0.0, 0.0) };
+    let tangent = glm::normalize(&glm::cross(&helper, normal));
+    let bitangent = glm::cross(normal, &tangent);
+    (tangent, bitangent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 5x5 grid of floor vertices next to a wall, so the vertices near the wall are partly occluded
+    fn floor_and_wall() -> Mesh {
+        let mut vertices = vec![];
+        let mut normals = vec![];
+        let mut indices = vec![];
+        for z in 0..5 {
+            for x in 0..5 {
+                vertices.extend_from_slice(&[x as f32 - 2.0, 0.0, z as f32 - 2.0]);
+                normals.extend_from_slice(&[0.0, 1.0, 0.0]);
+            }
+        }
+        for z in 0..4 {
+            for x in 0..4 {
+                let corner = z * 5 + x;
+                indices.extend_from_slice(&[corner, corner + 5, corner + 1, corner + 1, corner + 5, corner + 6]);
+            }
+        }
+        vertices.extend_from_slice(&[2.2, 0.0, -2.0, 2.2, 0.0, 2.0, 2.2, 4.0, 2.0, 2.2, 4.0, -2.0]);
+        normals.extend_from_slice(&[-1.0, 0.0, 0.0, -1.0, 0.0, 0.0, -1.0, 0.0, 0.0, -1.0, 0.0, 0.0]);
+        indices.extend_from_slice(&[25, 26, 27, 25, 27, 28]);
+        Mesh::from_arrays(vertices, normals, indices, [1.0, 1.0, 1.0, 1.0])
+    }
+
+    fn baked_colors(seed: u64) -> Vec<f32> {
+        let mut mesh = floor_and_wall();
+        let occlusion = AmbientOcclusion { samples: 32, max_distance: 10.0, seed };
+        let accessibility = occlusion.bake(&mesh);
+        darken_vertex_colors(&mut mesh, &accessibility);
+        mesh.colors
+    }
+
+    #[test]
+    fn same_seed_bakes_the_same_colors() {
+        assert_eq!(baked_colors(7), baked_colors(7));
+    }
+
+    #[test]
+    fn different_seeds_bake_different_colors() {
+        assert_ne!(baked_colors(7), baked_colors(8));
+    }
+}
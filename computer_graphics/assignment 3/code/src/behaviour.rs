@@ -0,0 +1,96 @@
+extern crate nalgebra_glm as glm;
+
+use crate::scene_graph::{NodeId, Scene};
+use crate::toolbox;
+
+// Behaviours are per-node update logic. A node carries any number of them, and
+// `Scene::update_behaviours` ticks every one in the hierarchy once per frame, so animating a
+// helicopter is a matter of attaching the right behaviours to its nodes instead of calling code
+// for it from the main loop.
+//
+// A behaviour gets the whole scene, not just its node, so it can also look at or move others.
+// Behaviours are code, they are not saved with the scene (see `scene_file`).
+
+pub struct FrameTime {
+    pub elapsed : f32,  // Seconds since the first frame
+    pub delta   : f32,  // Seconds since the previous frame
+}
+
+pub trait Behaviour {
+    // Called once per frame for the node `id` the behaviour is attached to
+    fn update(&mut self, scene: &mut Scene, id: NodeId, time: &FrameTime);
+}
+
+impl Scene {
+    // Ticks the behaviours of every node below the root, parents before their children. Detached
+    // nodes are not ticked. Behaviours may add and remove nodes, including their own.
+    pub fn update_behaviours(&mut self, time: &FrameTime) {
+        let ids: Vec<NodeId> = self.depth_first(self.root()).map(|visit| visit.id).collect();
+        for id in ids {
+            // Taken out of the node for the duration, so they can borrow the scene
+            let Some(mut behaviours) = self.take_behaviours(id) else { continue };
+            if behaviours.is_empty() {
+                continue;
+            }
+            for behaviour in &mut behaviours {
+                behaviour.update(self, id, time);
+                if !self.contains(id) {
+                    break;
+                }
+            }
+            self.return_behaviours(id, behaviours);
+        }
+    }
+}
+
+// Spins the node around its own axes, in radians per second around X, Y and Z
+pub struct Spinner {
+    pub angular_velocity : glm::Vec3,
+}
+
+impl Behaviour for Spinner {
+    fn update(&mut self, scene: &mut Scene, id: NodeId, time: &FrameTime) {
+        scene[id].rotation += self.angular_velocity * time.delta;
+    }
+}
+
+// Moves and turns the node along a path given as a heading for every point in time, like
+// `toolbox::simple_heading_animation`. The height is left alone.
+pub struct PathFollower {
+    pub path : fn(f32) -> toolbox::Heading,
+    pub time : f32,  // Where on the path the node is, start nodes at different times to spread them out
+}
+
+impl Behaviour for PathFollower {
+    fn update(&mut self, scene: &mut Scene, id: NodeId, time: &FrameTime) {
+        self.time += time.delta;
+        let heading = (self.path)(self.time);
+        let node = &mut scene[id];
+        node.position.x = heading.x;
+        node.position.z = heading.z;
+        node.rotation = glm::vec3(heading.pitch, heading.yaw, heading.roll);
+    }
+}
+
+// Bobs the node up and down around the height it had when it was first updated
+pub struct Bobbing {
+    pub amplitude : f32,          // How far up and down, from the middle
+    pub frequency : f32,          // Bobs per second
+    pub phase     : f32,          // In radians, to keep several bobbing nodes out of step
+    base_height   : Option<f32>,
+}
+
+impl Bobbing {
+    pub fn new(amplitude: f32, frequency: f32, phase: f32) -> Bobbing {
+        Bobbing { amplitude, frequency, phase, base_height: None }
+    }
+}
+
+impl Behaviour for Bobbing {
+    fn update(&mut self, scene: &mut Scene, id: NodeId, time: &FrameTime) {
+        let node = &mut scene[id];
+        let base_height = *self.base_height.get_or_insert(node.position.y);
+        let angle = 2.0 * std::f32::consts::PI * self.frequency * time.elapsed + self.phase;
+        node.position.y = base_height + self.amplitude * angle.sin();
+    }
+}
@@ -0,0 +1,49 @@
+extern crate nalgebra_glm as glm;
+
+use serde::{Deserialize, Serialize};
+
+use crate::scene_graph::{NodeId, Scene};
+
+// Cameras are nodes: a node with a `Camera` looks down its own -Z axis with +Y up, from wherever
+// its world transform puts it. Parent one to a helicopter for a view from the cockpit, or to a
+// pivot node to orbit around it. The view matrix is just the inverse of the camera's world matrix,
+// so scaling above a camera scales the view as well.
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Camera {
+    pub fov_y : f32,  // Vertical field of view, in radians
+    pub near  : f32,  // Distances to the clipping planes
+    pub far   : f32,
+}
+
+impl Camera {
+    pub fn new(fov_y: f32, near: f32, far: f32) -> Camera {
+        Camera { fov_y, near, far }
+    }
+
+    // `aspect_ratio` is the screen's width over its height
+    pub fn projection_matrix(&self, aspect_ratio: f32) -> glm::Mat4 {
+        glm::perspective(aspect_ratio, self.fov_y, self.near, self.far)
+    }
+}
+
+impl Scene {
+    // From world space to the space of the camera node `id`
+    pub fn view_matrix(&self, id: NodeId) -> glm::Mat4 {
+        glm::inverse(&self.world_matrix(id))
+    }
+
+    // What the camera node `id` sees on a screen with `aspect_ratio`, for drawing and picking
+    pub fn view_projection_matrix(&self, id: NodeId, aspect_ratio: f32) -> glm::Mat4 {
+        let camera = self[id].camera.as_ref().expect("The node to view the scene from has no camera");
+        camera.projection_matrix(aspect_ratio) * self.view_matrix(id)
+    }
+
+    // The nodes with a camera in the subtree below `start`, in depth first order
+    pub fn cameras(&self, start: NodeId) -> Vec<NodeId> {
+        self.depth_first(start)
+            .filter(|visit| visit.node.camera.is_some())
+            .map(|visit| visit.id)
+            .collect()
+    }
+}
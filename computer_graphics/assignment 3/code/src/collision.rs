@@ -0,0 +1,199 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashSet;
+
+use crate::geometry::Aabb;
+use crate::scene_graph::{NodeId, Scene};
+
+// Collision detection between scene nodes. A collider is a node and everything below it, e.g. a
+// whole helicopter, bounded by the world space bounds of its subtree's meshes (see
+// `Scene::subtree_bounds`) as either a box or the sphere around that box.
+//
+// `detect` runs once per frame: a broad phase sweeps the colliders' boxes along the X axis to
+// find the pairs that may touch, and a narrow phase tests those pairs' shapes and computes the
+// contact. It returns events rather than just the current contacts, so the animation or game
+// logic can react to a collision starting or ending instead of on every frame it lasts.
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Shape {
+    Box,     // The subtree's bounds
+    Sphere,  // The smallest sphere around the subtree's bounds
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    pub a      : NodeId,
+    pub b      : NodeId,
+    pub normal : glm::Vec3,  // Unit length, pointing from `a` towards `b`
+    pub depth  : f32,        // How far `b` has to move along the normal to stop touching `a`
+    pub point  : glm::Vec3,  // Halfway into the overlap, in world space
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum ContactEvent {
+    Began(Contact),
+    Persisted(Contact),
+    Ended(NodeId, NodeId),
+}
+
+pub struct CollisionDetector {
+    colliders : Vec<(NodeId, Shape)>,
+    touching  : HashSet<(NodeId, NodeId)>,  // The pairs in contact last frame, as (a, b) of their contact
+}
+
+impl CollisionDetector {
+    pub fn new() -> CollisionDetector {
+        CollisionDetector { colliders: vec![], touching: HashSet::new() }
+    }
+
+    // Makes the node and its subtree collide with the other colliders, replacing its shape if it
+    // already is one
+    pub fn add(&mut self, id: NodeId, shape: Shape) {
+        match self.colliders.iter_mut().find(|(collider, _)| *collider == id) {
+            Some((_, existing)) => *existing = shape,
+            None => self.colliders.push((id, shape)),
+        }
+    }
+
+    // Its contacts end on the next `detect`
+    #[allow(dead_code)]
+    pub fn remove(&mut self, id: NodeId) {
+        self.colliders.retain(|&(collider, _)| collider != id);
+    }
+
+    // The contacts between colliders that began, went on or ended since the last call. Removed
+    // nodes stop being colliders, detached ones are skipped until they are attached again, and
+    // colliders inside each other's subtrees are never tested.
+    pub fn detect(&mut self, scene: &Scene) -> Vec<ContactEvent> {
+        self.colliders.retain(|&(id, _)| scene.contains(id));
+
+        // Broad phase: colliders whose boxes overlap along X, in order of where they start
+        let mut boxes: Vec<(NodeId, Shape, Aabb)> = self.colliders.iter()
+            .filter(|&&(id, _)| scene.is_ancestor(scene.root(), id))
+            .map(|&(id, shape)| (id, shape, scene.subtree_bounds(id)))
+            .filter(|(_, _, bounds)| !bounds.is_empty())
+            .collect();
+        boxes.sort_by(|a, b| a.2.min.x.partial_cmp(&b.2.min.x).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut events = vec![];
+        let mut touching = HashSet::new();
+        for (i, &(a, shape_a, bounds_a)) in boxes.iter().enumerate() {
+            for &(b, shape_b, bounds_b) in &boxes[i + 1..] {
+                if bounds_b.min.x > bounds_a.max.x {
+                    break; // Neither does anything after it
+                }
+                if scene.is_ancestor(a, b) || scene.is_ancestor(b, a) {
+                    continue;
+                }
+
+                // Narrow phase, keeping the pair in the order it was first seen in
+                let (a, b, shape_a, shape_b, bounds_a, bounds_b) = if self.touching.contains(&(b, a)) {
+                    (b, a, shape_b, shape_a, bounds_b, bounds_a)
+                } else {
+                    (a, b, shape_a, shape_b, bounds_a, bounds_b)
+                };
+                let Some((normal, depth, point)) = test_shapes(shape_a, &bounds_a, shape_b, &bounds_b) else {
+                    continue;
+                };
+                let contact = Contact { a, b, normal, depth, point };
+                touching.insert((a, b));
+                events.push(if self.touching.contains(&(a, b)) {
+                    ContactEvent::Persisted(contact)
+                } else {
+                    ContactEvent::Began(contact)
+                });
+            }
+        }
+
+        for &(a, b) in self.touching.difference(&touching) {
+            events.push(ContactEvent::Ended(a, b));
+        }
+        self.touching = touching;
+        events
+    }
+}
+
+fn bounding_sphere(bounds: &Aabb) -> (glm::Vec3, f32) {
+    (bounds.center(), glm::length(&bounds.extent()) * 0.5)
+}
+
+// The normal from the first shape to the second, the depth and the point of the contact, if any
+fn test_shapes(shape_a: Shape, a: &Aabb, shape_b: Shape, b: &Aabb) -> Option<(glm::Vec3, f32, glm::Vec3)> {
+    match (shape_a, shape_b) {
+        (Shape::Box, Shape::Box) => box_box(a, b),
+        (Shape::Sphere, Shape::Sphere) => {
+            let (center_a, radius_a) = bounding_sphere(a);
+            let (center_b, radius_b) = bounding_sphere(b);
+            sphere_sphere(&center_a, radius_a, &center_b, radius_b)
+        }
+        (Shape::Sphere, Shape::Box) => {
+            let (center, radius) = bounding_sphere(a);
+            sphere_box(&center, radius, b)
+        }
+        (Shape::Box, Shape::Sphere) => {
+            let (center, radius) = bounding_sphere(b);
+            sphere_box(&center, radius, a).map(|(normal, depth, point)| (-normal, depth, point))
+        }
+    }
+}
+
+fn sphere_sphere(center_a: &glm::Vec3, radius_a: f32, center_b: &glm::Vec3, radius_b: f32) -> Option<(glm::Vec3, f32, glm::Vec3)> {
+    let offset = center_b - center_a;
+    let distance = glm::length(&offset);
+    let depth = radius_a + radius_b - distance;
+    if depth < 0.0 {
+        return None;
+    }
+    // Spheres on top of each other have no direction to separate in, pick one
+    let normal = if distance > 0.0 { offset / distance } else { glm::vec3(0.0, 1.0, 0.0) };
+    Some((normal, depth, center_a + normal * (radius_a - depth * 0.5)))
+}
+
+fn sphere_box(center: &glm::Vec3, radius: f32, bounds: &Aabb) -> Option<(glm::Vec3, f32, glm::Vec3)> {
+    let closest = glm::clamp_vec(center, &bounds.min, &bounds.max);
+    let offset = closest - center;
+    let distance = glm::length(&offset);
+    if distance > radius {
+        return None;
+    }
+    if distance > 0.0 {
+        let normal = offset / distance;
+        let depth = radius - distance;
+        return Some((normal, depth, closest - normal * (depth * 0.5)));
+    }
+    // The center is inside the box, which gets pushed away past the face nearest to it
+    let mut normal = glm::zero::<glm::Vec3>();
+    let mut depth = f32::INFINITY;
+    for axis in 0..3 {
+        let to_min = center[axis] - bounds.min[axis];
+        let to_max = bounds.max[axis] - center[axis];
+        if to_min < depth {
+            depth = to_min;
+            normal = glm::zero();
+            normal[axis] = 1.0;
+        }
+        if to_max < depth {
+            depth = to_max;
+            normal = glm::zero();
+            normal[axis] = -1.0;
+        }
+    }
+    Some((normal, depth + radius, *center))
+}
+
+fn box_box(a: &Aabb, b: &Aabb) -> Option<(glm::Vec3, f32, glm::Vec3)> {
+    if !a.intersects(b) {
+        return None;
+    }
+    // Separate along the axis with the least overlap
+    let overlap_min = glm::max2(&a.min, &b.min);
+    let overlap_max = glm::min2(&a.max, &b.max);
+    let overlap = overlap_max - overlap_min;
+    let axis = if overlap.x <= overlap.y && overlap.x <= overlap.z { 0 } else if overlap.y <= overlap.z { 1 } else { 2 };
+    let mut normal = glm::zero::<glm::Vec3>();
+    normal[axis] = if b.center()[axis] >= a.center()[axis] { 1.0 } else { -1.0 };
+    Some((normal, overlap[axis], (overlap_min + overlap_max) * 0.5))
+}
@@ -0,0 +1,160 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::BTreeSet;
+use std::f32::consts::PI;
+use std::io::Write;
+
+use crate::edges::MeshEdges;
+use crate::mesh::Mesh;
+
+// Per-vertex scalar fields describing the shape of a surface, meant for terrain analysis:
+//  * mean curvature, from the cotangent Laplacian (positive on hills, negative in craters)
+//  * Gaussian curvature, from the angle deficit (positive on domes, negative on saddles)
+//  * slope, the angle in radians between the surface normal and the up (+Y) axis
+//  * roughness, the RMS distance of the neighbouring vertices to the vertex's tangent plane
+//
+// Everything is computed on the welded mesh (see `Mesh::welded_vertices`) so normal seams don't
+// cut the surface apart, and then copied back to every vertex. Curvature is not well defined on
+// open borders, so boundary vertices get 0 for both curvatures.
+
+pub struct SurfaceAnalysis {
+    pub mean_curvature     : Vec<f32>,
+    pub gaussian_curvature : Vec<f32>,
+    pub slope              : Vec<f32>,
+    pub roughness          : Vec<f32>,
+}
+
+impl SurfaceAnalysis {
+    #[allow(dead_code)]
+    pub fn from_mesh(mesh: &Mesh) -> SurfaceAnalysis {
+        let vertex_count = mesh.vertex_count();
+        let welded = mesh.welded_vertices();
+
+        let mut area       = vec![0.0f32; vertex_count];  // A third of the surrounding faces
+        let mut angle_sum  = vec![0.0f32; vertex_count];
+        let mut laplacian  = vec![glm::zero::<glm::Vec3>(); vertex_count];
+        let mut normal     = vec![glm::zero::<glm::Vec3>(); vertex_count];
+        let mut neighbours = vec![BTreeSet::new(); vertex_count];
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            let corners = [welded[triangle[0] as usize], welded[triangle[1] as usize], welded[triangle[2] as usize]];
+            if corners[0] == corners[1] || corners[1] == corners[2] || corners[2] == corners[0] {
+                continue;
+            }
+            let positions = corners.map(|vertex| mesh.position(vertex));
+            let face_normal = glm::cross(&(positions[1] - positions[0]), &(positions[2] - positions[0]));
+            let face_area = glm::length(&face_normal) * 0.5;
+            if face_area == 0.0 {
+                continue;
+            }
+
+            for corner in 0..3 {
+                let i = corners[corner] as usize;
+                let j = corners[(corner + 1) % 3] as usize;
+                let k = corners[(corner + 2) % 3] as usize;
+                let (p_i, p_j, p_k) = (positions[corner], positions[(corner + 1) % 3], positions[(corner + 2) % 3]);
+
+                area[i] += face_area / 3.0;
+                normal[i] += face_normal; // Area weighted, the cross product is twice the area
+                angle_sum[i] += angle_between(&(p_j - p_i), &(p_k - p_i));
+                neighbours[i].insert(j);
+                neighbours[i].insert(k);
+
+                // The angle at this corner weighs the opposite edge j-k
+                let cotangent = cotangent(&(p_j - p_i), &(p_k - p_i));
+                laplacian[j] += (p_k - p_j) * (cotangent * 0.5);
+                laplacian[k] += (p_j - p_k) * (cotangent * 0.5);
+            }
+        }
+
+        let mut boundary = vec![false; vertex_count];
+        for edge in MeshEdges::from_mesh(mesh).edges.iter().filter(|edge| edge.faces.len() == 1) {
+            boundary[edge.vertices[0] as usize] = true;
+            boundary[edge.vertices[1] as usize] = true;
+        }
+
+        let mut analysis = SurfaceAnalysis {
+            mean_curvature: vec![0.0; vertex_count],
+            gaussian_curvature: vec![0.0; vertex_count],
+            slope: vec![0.0; vertex_count],
+            roughness: vec![0.0; vertex_count],
+        };
+
+        for vertex in 0..vertex_count {
+            let original = welded[vertex] as usize;
+            if original != vertex {
+                // Seam duplicates always come after their original, which is done by now
+                analysis.mean_curvature[vertex] = analysis.mean_curvature[original];
+                analysis.gaussian_curvature[vertex] = analysis.gaussian_curvature[original];
+                analysis.slope[vertex] = analysis.slope[original];
+                analysis.roughness[vertex] = analysis.roughness[original];
+                continue;
+            }
+            if area[vertex] == 0.0 || glm::length(&normal[vertex]) == 0.0 {
+                continue; // Not part of any triangle
+            }
+
+            let n = glm::normalize(&normal[vertex]);
+            let p = mesh.position(vertex as u32);
+
+            if !boundary[vertex] {
+                // Δp ≈ -2Hn, with the laplacian normalized by the vertex area
+                let mean_curvature_normal = laplacian[vertex] / area[vertex];
+                analysis.mean_curvature[vertex] = -glm::dot(&mean_curvature_normal, &n) * 0.5;
+                analysis.gaussian_curvature[vertex] = (2.0 * PI - angle_sum[vertex]) / area[vertex];
+            }
+            analysis.slope[vertex] = n.y.clamp(-1.0, 1.0).acos();
+
+            let squared_distances: f32 = neighbours[vertex].iter()
+                .map(|&neighbour| glm::dot(&(mesh.position(neighbour as u32) - p), &n).powi(2))
+                .sum();
+            analysis.roughness[vertex] = (squared_distances / neighbours[vertex].len() as f32).sqrt();
+        }
+
+        analysis
+    }
+
+    // Write all fields as CSV, one row per vertex, for use in other tools
+    #[allow(dead_code)]
+    pub fn write_csv(&self, path: &str) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(file, "vertex,mean_curvature,gaussian_curvature,slope,roughness")?;
+        for vertex in 0..self.slope.len() {
+            writeln!(file, "{},{},{},{},{}",
+                vertex,
+                self.mean_curvature[vertex],
+                self.gaussian_curvature[vertex],
+                self.slope[vertex],
+                self.roughness[vertex],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Map a scalar field to RGBA vertex colors (blue at `min`, through green, to red at `max`), in
+// the layout expected by `Mesh::colors`
+#[allow(dead_code)]
+pub fn scalar_field_colors(values: &[f32], min: f32, max: f32) -> Vec<f32> {
+    let range = if max > min { max - min } else { 1.0 };
+    values.iter()
+        .flat_map(|&value| {
+            let t = ((value - min) / range).clamp(0.0, 1.0);
+            let red = (2.0 * t - 1.0).max(0.0);
+            let blue = (1.0 - 2.0 * t).max(0.0);
+            let green = 1.0 - red - blue;
+            [red, green, blue, 1.0]
+        })
+        .collect()
+}
+
+// internal helpers
+
+fn angle_between(a: &glm::Vec3, b: &glm::Vec3) -> f32 {
+    glm::length(&glm::cross(a, b)).atan2(glm::dot(a, b))
+}
+
+fn cotangent(a: &glm::Vec3, b: &glm::Vec3) -> f32 {
+    let sine = glm::length(&glm::cross(a, b));
+    if sine == 0.0 { 0.0 } else { glm::dot(a, b) / sine }
+}
@@ -0,0 +1,93 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::BTreeMap;
+
+use crate::mesh::Mesh;
+
+// Line geometry derived from the triangles of a `Mesh`. Every function here produces pairs of
+// indices into the mesh's own vertex arrays, so the result can be uploaded as the index buffer of a
+// VAO built from the same vertices and drawn with `gl::LINES`.
+//
+// Edges are matched on welded vertices (see `Mesh::welded_vertices`), otherwise every normal seam
+// would show up as a boundary. The first vertex at a given position is the one in the output.
+
+pub struct Edge {
+    pub vertices       : [u32; 2],    // Lowest index first
+    pub faces          : Vec<usize>,  // The triangles sharing this edge
+    pub dihedral_angle : f32,         // Angle between the normals of the two faces, in radians
+}
+
+pub struct MeshEdges {
+    pub edges: Vec<Edge>,
+}
+
+impl MeshEdges {
+    #[allow(dead_code)]
+    pub fn from_mesh(mesh: &Mesh) -> MeshEdges {
+        let welded = mesh.welded_vertices();
+
+        let face_normals: Vec<glm::Vec3> = mesh.indices.chunks_exact(3)
+            .map(|triangle| {
+                let a = mesh.position(triangle[0]);
+                let b = mesh.position(triangle[1]);
+                let c = mesh.position(triangle[2]);
+                let normal = glm::cross(&(b - a), &(c - a));
+                // Degenerate triangles get a zero normal, and never form a crease
+                if glm::length(&normal) > 0.0 { glm::normalize(&normal) } else { normal }
+            })
+            .collect();
+
+        // A BTreeMap keeps the output order stable between runs
+        let mut faces_per_edge: BTreeMap<[u32; 2], Vec<usize>> = BTreeMap::new();
+        for (face, triangle) in mesh.indices.chunks_exact(3).enumerate() {
+            for corner in 0..3 {
+                let a = welded[triangle[corner] as usize];
+                let b = welded[triangle[(corner + 1) % 3] as usize];
+                if a == b {
+                    continue;
+                }
+                faces_per_edge.entry([a.min(b), a.max(b)]).or_default().push(face);
+            }
+        }
+
+        let edges = faces_per_edge.into_iter()
+            .map(|(vertices, faces)| {
+                let dihedral_angle = if faces.len() == 2 {
+                    let cosine = glm::dot(&face_normals[faces[0]], &face_normals[faces[1]]);
+                    cosine.clamp(-1.0, 1.0).acos()
+                } else {
+                    0.0
+                };
+                Edge { vertices, faces, dihedral_angle }
+            })
+            .collect();
+
+        MeshEdges { edges }
+    }
+
+    // Every edge of the mesh exactly once, i.e. a wireframe
+    #[allow(dead_code)]
+    pub fn unique(&self) -> Vec<u32> {
+        line_indices(self.edges.iter())
+    }
+
+    // Edges used by a single triangle, i.e. the outline of holes and open borders
+    #[allow(dead_code)]
+    pub fn boundary(&self) -> Vec<u32> {
+        line_indices(self.edges.iter().filter(|edge| edge.faces.len() == 1))
+    }
+
+    // Creases where the two neighbouring faces bend by more than `angle_threshold` radians.
+    // Non-manifold edges (shared by more than two faces) are always included.
+    #[allow(dead_code)]
+    pub fn feature(&self, angle_threshold: f32) -> Vec<u32> {
+        line_indices(self.edges.iter().filter(|edge| {
+            edge.faces.len() > 2 || (edge.faces.len() == 2 && edge.dihedral_angle > angle_threshold)
+        }))
+    }
+}
+
+// internal helper
+fn line_indices<'a>(edges: impl Iterator<Item = &'a Edge>) -> Vec<u32> {
+    edges.flat_map(|edge| edge.vertices).collect()
+}
@@ -0,0 +1,342 @@
+extern crate nalgebra_glm as glm;
+
+use crate::mesh::Mesh;
+
+// Small geometric building blocks: rays, axis aligned boxes, view frustums, and a bounding volume
+// hierarchy over the triangles of a `Mesh` for answering ray queries without testing every
+// triangle.
+
+#[derive(Clone, Copy)]
+pub struct Ray {
+    pub origin    : glm::Vec3,
+    pub direction : glm::Vec3,  // Not required to be normalized, distances are measured in units of it
+}
+
+impl Ray {
+    pub fn new(origin: glm::Vec3, direction: glm::Vec3) -> Ray {
+        Ray { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> glm::Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+
+impl Aabb {
+    // An inverted box which any point or box will replace when grown
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: glm::vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: glm::vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn grow(&mut self, point: &glm::Vec3) {
+        self.min = glm::min2(&self.min, point);
+        self.max = glm::max2(&self.max, point);
+    }
+
+    pub fn merge(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: glm::min2(&self.min, &other.min),
+            max: glm::max2(&self.max, &other.max),
+        }
+    }
+
+    // The box around this box after an affine transform, e.g. a model matrix
+    pub fn transformed(&self, matrix: &glm::Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        let mut bounds = Aabb::empty();
+        for corner in 0..8 {
+            let point = glm::vec4(
+                if corner & 1 == 0 { self.min.x } else { self.max.x },
+                if corner & 2 == 0 { self.min.y } else { self.max.y },
+                if corner & 4 == 0 { self.min.z } else { self.max.z },
+                1.0,
+            );
+            bounds.grow(&(matrix * point).xyz());
+        }
+        bounds
+    }
+
+    pub fn center(&self) -> glm::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> glm::Vec3 {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let extent = self.extent();
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    // The box grown by `margin` on every side
+    pub fn expanded(&self, margin: f32) -> Aabb {
+        let margin = glm::vec3(margin, margin, margin);
+        Aabb { min: self.min - margin, max: self.max + margin }
+    }
+
+    // Whether `other` is entirely inside this box
+    pub fn contains(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.min[axis] && other.max[axis] <= self.max[axis])
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.max[axis] && other.min[axis] <= self.max[axis])
+    }
+
+    pub fn intersects_sphere(&self, center: &glm::Vec3, radius: f32) -> bool {
+        let closest = glm::clamp_vec(center, &self.min, &self.max);
+        glm::distance2(&closest, center) <= radius * radius
+    }
+
+    // Slab test, returns the distance along the ray at which it enters the box, if it does so
+    // before `max_t`. Rays starting inside the box enter it at 0.
+    pub fn intersect_ray(&self, ray: &Ray, max_t: f32) -> Option<f32> {
+        let mut t_near = 0.0f32;
+        let mut t_far = max_t;
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN (a zero direction component on the slab boundary) leaves the interval untouched
+            t_near = if t0 > t_near { t0 } else { t_near };
+            t_far = if t1 < t_far { t1 } else { t_far };
+            if t_near > t_far {
+                return None;
+            }
+        }
+        Some(t_near)
+    }
+}
+
+// The part of space a camera sees, as six planes (x, y, z, w) facing inwards: a point p is on the
+// inside of a plane when dot(xyz, p) + w >= 0
+pub struct Frustum {
+    planes: [glm::Vec4; 6],
+}
+
+impl Frustum {
+    // Gribb and Hartmann's method, the planes are sums and differences of the matrix's rows.
+    // With a view projection matrix the planes are in world space.
+    pub fn from_matrix(matrix: &glm::Mat4) -> Frustum {
+        let row = |i: usize| matrix.row(i).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Frustum {
+            planes: [w + x, w - x, w + y, w - y, w + z, w - z],
+        }
+    }
+
+    // Whether any of the box may be inside. Boxes just outside a corner, where no single plane
+    // has them all on its outside, count as inside too, which only costs a wasted draw.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let corner = glm::vec3(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            glm::dot(&plane.xyz(), &corner) + plane.w >= 0.0
+        })
+    }
+}
+
+// Möller–Trumbore, two sided. Returns the distance along the ray to the hit point.
+pub fn intersect_triangle(ray: &Ray, a: &glm::Vec3, b: &glm::Vec3, c: &glm::Vec3) -> Option<f32> {
+    let epsilon = 1e-7;
+    let edge_1 = b - a;
+    let edge_2 = c - a;
+    let p = glm::cross(&ray.direction, &edge_2);
+    let determinant = glm::dot(&edge_1, &p);
+    if determinant.abs() < epsilon {
+        return None; // Parallel to the triangle
+    }
+    let inverse_determinant = 1.0 / determinant;
+
+    let s = ray.origin - a;
+    let u = glm::dot(&s, &p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = glm::cross(&s, &edge_1);
+    let v = glm::dot(&ray.direction, &q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = glm::dot(&edge_2, &q) * inverse_determinant;
+    if t > epsilon { Some(t) } else { None }
+}
+
+// Triangle BVH
+
+const LEAF_SIZE: usize = 4;
+
+struct BvhNode {
+    bounds : Aabb,
+    start  : usize,  // Leaves: first entry in `triangles`. Inner nodes: index of the left child
+    count  : usize,  // Leaves: number of triangles. Inner nodes: 0
+    right  : usize,  // Inner nodes: index of the right child
+}
+
+pub struct TriangleBvh {
+    positions : Vec<glm::Vec3>,
+    indices   : Vec<u32>,
+    triangles : Vec<usize>,  // Triangle numbers, reordered so every leaf covers a contiguous range
+    nodes     : Vec<BvhNode>,
+}
+
+#[allow(dead_code)]
+pub struct RayHit {
+    pub distance : f32,
+    pub triangle : usize,  // Which triangle of the mesh was hit, i.e. `indices[3*triangle..3*triangle+3]`
+}
+
+impl TriangleBvh {
+    pub fn from_mesh(mesh: &Mesh) -> TriangleBvh {
+        let positions: Vec<glm::Vec3> = (0..mesh.vertices.len() / 3)
+            .map(|i| mesh.position(i as u32))
+            .collect();
+        let mut bvh = TriangleBvh {
+            positions,
+            indices: mesh.indices.clone(),
+            triangles: (0..mesh.indices.len() / 3).collect(),
+            nodes: vec![],
+        };
+        let centroids: Vec<glm::Vec3> = (0..bvh.triangles.len())
+            .map(|triangle| bvh.triangle_bounds(triangle).center())
+            .collect();
+        let triangle_count = bvh.triangles.len();
+        if triangle_count > 0 {
+            bvh.build(0, triangle_count, &centroids);
+        }
+        bvh
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map(|node| node.bounds).unwrap_or(Aabb::empty())
+    }
+
+    fn triangle_vertices(&self, triangle: usize) -> [glm::Vec3; 3] {
+        [
+            self.positions[self.indices[3 * triangle] as usize],
+            self.positions[self.indices[3 * triangle + 1] as usize],
+            self.positions[self.indices[3 * triangle + 2] as usize],
+        ]
+    }
+
+    fn triangle_bounds(&self, triangle: usize) -> Aabb {
+        let mut bounds = Aabb::empty();
+        for vertex in &self.triangle_vertices(triangle) {
+            bounds.grow(vertex);
+        }
+        bounds
+    }
+
+    // Builds the subtree over `triangles[start..end]` and returns the index of its root
+    fn build(&mut self, start: usize, end: usize, centroids: &[glm::Vec3]) -> usize {
+        let mut bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for &triangle in &self.triangles[start..end] {
+            bounds = bounds.merge(&self.triangle_bounds(triangle));
+            centroid_bounds.grow(&centroids[triangle]);
+        }
+
+        let node = self.nodes.len();
+        self.nodes.push(BvhNode { bounds, start, count: end - start, right: 0 });
+        if end - start <= LEAF_SIZE {
+            return node;
+        }
+
+        // Median split along the longest axis of the centroids
+        let extent = centroid_bounds.extent();
+        let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+        let middle = (start + end) / 2;
+        self.triangles[start..end].select_nth_unstable_by(middle - start, |&a, &b| {
+            centroids[a][axis].partial_cmp(&centroids[b][axis]).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let left = self.build(start, middle, centroids);
+        let right = self.build(middle, end, centroids);
+        self.nodes[node].start = left;
+        self.nodes[node].count = 0;
+        self.nodes[node].right = right;
+        node
+    }
+
+    // The closest triangle hit before `max_distance`
+    pub fn closest_hit(&self, ray: &Ray, max_distance: f32) -> Option<RayHit> {
+        let mut closest: Option<RayHit> = None;
+        self.traverse(ray, max_distance, |triangle, distance| {
+            if closest.as_ref().is_none_or(|hit| distance < hit.distance) {
+                closest = Some(RayHit { distance, triangle });
+            }
+            false
+        });
+        closest
+    }
+
+    // Whether anything is hit before `max_distance`, stops at the first hit found
+    pub fn any_hit(&self, ray: &Ray, max_distance: f32) -> bool {
+        let mut hit = false;
+        self.traverse(ray, max_distance, |_, _| {
+            hit = true;
+            true
+        });
+        hit
+    }
+
+    // Calls `on_hit` with every triangle hit closer than the best distance so far, until it returns true
+    fn traverse<F: FnMut(usize, f32) -> bool>(&self, ray: &Ray, max_distance: f32, mut on_hit: F) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut best = max_distance;
+        let mut stack = vec![0usize];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if node.bounds.intersect_ray(ray, best).is_none() {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.right);
+                stack.push(node.start);
+                continue;
+            }
+            for &triangle in &self.triangles[node.start..node.start + node.count] {
+                let [a, b, c] = self.triangle_vertices(triangle);
+                if let Some(distance) = intersect_triangle(ray, &a, &b, &c) {
+                    if distance < best {
+                        best = distance;
+                        if on_hit(triangle, distance) {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+extern crate nalgebra_glm as glm;
+
+use std::os::raw::c_void;
+use std::ptr;
+use std::rc::Rc;
+
+use crate::geometry::{Aabb, TriangleBvh};
+use crate::mesh::{Mesh, MeshRef};
+
+// A mesh uploaded to the GPU. It is uploaded once and shared between all the nodes that draw it
+// through an `Rc`, and its VAO and buffers are deleted when the last of them lets go. Since the
+// GL objects belong to the render thread's context, so do these.
+//
+// Besides the vertex data from `create_vao`, the VAO has a buffer of per-instance data (see
+// `Instance`), so all nodes sharing a mesh can be drawn with one instanced draw call. A copy of
+// the triangles stays on the CPU for ray casts, see `picking`.
+
+// What differs between the copies of a mesh in one draw call: attributes 3 to 6 (a mat4 takes
+// four), 7 and 8 in the shaders
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Instance {
+    pub model_matrix : glm::Mat4,
+    pub tint         : glm::Vec4,  // Multiplies the vertex colors
+    pub emissive     : glm::Vec3,  // Added after lighting
+}
+
+pub struct GpuMesh {
+    pub vao_id      : u32,
+    pub index_count : i32,
+    pub source      : Option<MeshRef>,  // Where the mesh was loaded from, for saving scenes
+    pub bounds      : Aabb,             // In the mesh's own space
+    pub triangles   : TriangleBvh,      // In the mesh's own space
+    instance_vbo    : u32,
+}
+
+impl GpuMesh {
+    pub unsafe fn upload(mesh: &Mesh) -> Rc<GpuMesh> {
+        let vao_id = crate::create_vao(&mesh.vertices, &mesh.indices, &mesh.colors, &mesh.normals);
+        gl::BindVertexArray(vao_id);
+
+        let mut instance_vbo: u32 = 0;
+        gl::GenBuffers(1, &mut instance_vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, instance_vbo);
+        // (attribute, number of floats, offset in floats), the matrix goes in column by column
+        let layout = [(3, 4, 0), (4, 4, 4), (5, 4, 8), (6, 4, 12), (7, 4, 16), (8, 3, 20)];
+        for (attribute, components, offset) in layout {
+            gl::EnableVertexAttribArray(attribute);
+            gl::VertexAttribPointer(
+                attribute,
+                components,
+                gl::FLOAT,
+                gl::FALSE,
+                crate::size_of::<Instance>(),      // From one instance to the next
+                crate::offset::<f32>(offset),
+            );
+            gl::VertexAttribDivisor(attribute, 1); // Advance once per instance, not per vertex
+        }
+        gl::BindVertexArray(0);
+
+        let triangles = TriangleBvh::from_mesh(mesh);
+        Rc::new(GpuMesh {
+            vao_id,
+            index_count: mesh.index_count,
+            source: mesh.source.clone(),
+            bounds: triangles.bounds(),
+            triangles,
+            instance_vbo,
+        })
+    }
+
+    // Draws one copy of the mesh per instance
+    pub unsafe fn draw_instanced(&self, instances: &[Instance]) {
+        if instances.is_empty() || self.index_count <= 0 {
+            return;
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            std::mem::size_of_val(instances) as isize,
+            instances.as_ptr() as *const c_void,
+            gl::STREAM_DRAW,
+        );
+
+        gl::BindVertexArray(self.vao_id);
+        gl::DrawElementsInstanced(
+            gl::TRIANGLES,
+            self.index_count,
+            gl::UNSIGNED_INT,
+            ptr::null(),
+            instances.len() as i32,
+        );
+    }
+}
+
+impl Drop for GpuMesh {
+    fn drop(&mut self) {
+        unsafe {
+            // `create_vao` does not hand out its buffers, so ask the VAO which ones it uses
+            let mut buffers = vec![self.instance_vbo];
+            gl::BindVertexArray(self.vao_id);
+            for attribute in 0..3 {
+                let mut buffer: i32 = 0;
+                gl::GetVertexAttribiv(attribute, gl::VERTEX_ATTRIB_ARRAY_BUFFER_BINDING, &mut buffer);
+                buffers.push(buffer as u32);
+            }
+            let mut index_buffer: i32 = 0;
+            gl::GetIntegerv(gl::ELEMENT_ARRAY_BUFFER_BINDING, &mut index_buffer);
+            buffers.push(index_buffer as u32);
+            gl::BindVertexArray(0);
+
+            gl::DeleteVertexArrays(1, &self.vao_id);
+            gl::DeleteBuffers(buffers.len() as i32, buffers.as_ptr());
+        }
+    }
+}
@@ -0,0 +1,342 @@
+extern crate nalgebra_glm as glm;
+
+use std::rc::Rc;
+
+use crate::camera::Camera;
+use crate::gpu_mesh::GpuMesh;
+use crate::light::Light;
+use crate::material::Material;
+use crate::rotation::EulerOrder;
+use crate::scene_graph::{NodeId, Scene, SceneNode};
+
+// Undo and redo for edits to a scene. Edits go through a `History` instead of straight to the
+// scene, which applies them and records them as commands that can be reverted and applied again:
+//  * `edit` changes a node's own properties (transform, name, mesh, material, ...) in a closure
+//  * `add_child`, `remove` and `reparent` change the hierarchy
+//
+// Removed nodes are only detached, so they keep their ids and every command referring to them
+// stays valid. They are freed once no command in the history can bring them back.
+//
+// One undo step is an entry of one or more commands. Edits between `begin_group` and `end_group`
+// make a single entry, and so do consecutive `edit`s of the same node, e.g. every frame of
+// dragging it around, until `seal` is called or something else is recorded.
+
+// The properties of a node outside of the hierarchy
+#[derive(Clone)]
+struct NodeState {
+    name            : String,
+    position        : glm::Vec3,
+    rotation        : glm::Vec3,
+    rotation_order  : EulerOrder,
+    orientation     : Option<glm::Quat>,
+    scale           : glm::Vec3,
+    reference_point : glm::Vec3,
+    mesh            : Option<Rc<GpuMesh>>,
+    visible         : bool,
+    layers          : u32,
+    material        : Option<Material>,
+    camera          : Option<Camera>,
+    light           : Option<Light>,
+}
+
+impl NodeState {
+    fn capture(node: &SceneNode) -> NodeState {
+        NodeState {
+            name: node.name.clone(),
+            position: node.position,
+            rotation: node.rotation,
+            rotation_order: node.rotation_order,
+            orientation: node.orientation,
+            scale: node.scale,
+            reference_point: node.reference_point,
+            mesh: node.mesh.clone(),
+            visible: node.visible,
+            layers: node.layers,
+            material: node.material.clone(),
+            camera: node.camera,
+            light: node.light,
+        }
+    }
+
+    // Changes the properties that differ between `from` and `to` to their value in `to`, leaving
+    // the rest alone. Nodes are also changed outside the history (by the animations), and undoing
+    // an edit should not revert those.
+    fn apply_difference(from: &NodeState, to: &NodeState, node: &mut SceneNode) {
+        if from.name != to.name {
+            node.name = to.name.clone();
+        }
+        if from.position != to.position {
+            node.position = to.position;
+        }
+        if from.rotation != to.rotation {
+            node.rotation = to.rotation;
+        }
+        if from.rotation_order != to.rotation_order {
+            node.rotation_order = to.rotation_order;
+        }
+        if from.orientation != to.orientation {
+            node.orientation = to.orientation;
+        }
+        if from.scale != to.scale {
+            node.scale = to.scale;
+        }
+        if from.reference_point != to.reference_point {
+            node.reference_point = to.reference_point;
+        }
+        let same_mesh = match (&from.mesh, &to.mesh) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        if !same_mesh {
+            node.mesh = to.mesh.clone();
+        }
+        if from.visible != to.visible {
+            node.visible = to.visible;
+        }
+        if from.layers != to.layers {
+            node.layers = to.layers;
+        }
+        if from.material != to.material {
+            node.material = to.material.clone();
+        }
+        if from.camera != to.camera {
+            node.camera = to.camera;
+        }
+        if from.light != to.light {
+            node.light = to.light;
+        }
+    }
+}
+
+// Where a node hangs in the hierarchy: its parent and its index among the parent's children, or
+// nothing for a detached node
+type Placement = Option<(NodeId, usize)>;
+
+enum Command {
+    Edit { id: NodeId, before: Box<NodeState>, after: Box<NodeState> },
+    Move { id: NodeId, before: Placement, after: Placement },
+}
+
+impl Command {
+    fn undo(&self, scene: &mut Scene) {
+        match self {
+            Command::Edit { id, before, after } => NodeState::apply_difference(after, before, &mut scene[*id]),
+            Command::Move { id, before, .. } => place(scene, *id, *before),
+        }
+    }
+
+    fn redo(&self, scene: &mut Scene) {
+        match self {
+            Command::Edit { id, before, after } => NodeState::apply_difference(before, after, &mut scene[*id]),
+            Command::Move { id, after, .. } => place(scene, *id, *after),
+        }
+    }
+
+    // Whether undoing or redoing the command touches any of the nodes, as the node it changes or
+    // as a parent it puts that node under
+    fn refers_to(&self, nodes: &[NodeId]) -> bool {
+        match self {
+            Command::Edit { id, .. } => nodes.contains(id),
+            Command::Move { id, before, after } => {
+                let mut parents = before.iter().chain(after.iter()).map(|(parent, _)| parent);
+                nodes.contains(id) || parents.any(|parent| nodes.contains(parent))
+            }
+        }
+    }
+}
+
+fn placement(scene: &Scene, id: NodeId) -> Placement {
+    Some((scene.parent(id)?, scene.child_index(id)?))
+}
+
+fn place(scene: &mut Scene, id: NodeId, placement: Placement) {
+    match placement {
+        Some((parent, index)) => {
+            scene.reparent(id, parent, false);
+            scene.set_child_index(id, index);
+        }
+        None => scene.detach(id, false),
+    }
+}
+
+struct Entry {
+    label    : String,
+    commands : Vec<Command>,  // In the order they were applied, undone in reverse
+    sealed   : bool,          // Whether later edits may still be merged into it
+}
+
+pub struct History {
+    undo_stack  : Vec<Entry>,
+    redo_stack  : Vec<Entry>,
+    group       : Option<Entry>,  // Collects the commands between `begin_group` and `end_group`
+    group_depth : usize,
+    pub limit   : usize,          // How many entries can be undone, the oldest are forgotten
+}
+
+impl History {
+    pub fn new() -> History {
+        History { undo_stack: vec![], redo_stack: vec![], group: None, group_depth: 0, limit: 100 }
+    }
+
+    // Changes the node's properties in `change`. Changes to its parent or children are not
+    // recorded, use the methods below for those.
+    pub fn edit<F: FnOnce(&mut SceneNode)>(&mut self, scene: &mut Scene, id: NodeId, label: &str, change: F) {
+        let before = NodeState::capture(&scene[id]);
+        change(&mut scene[id]);
+        let after = NodeState::capture(&scene[id]);
+
+        // Fold into the previous edit of the same node, keeping its state from before
+        if self.group.is_none() {
+            if let Some(entry) = self.undo_stack.last_mut().filter(|entry| !entry.sealed) {
+                if let [Command::Edit { id: previous, after: previous_after, .. }] = entry.commands.as_mut_slice() {
+                    if *previous == id {
+                        **previous_after = after;
+                        self.clear_redo(scene);
+                        return;
+                    }
+                }
+            }
+        }
+        self.record(scene, label, Command::Edit { id, before: Box::new(before), after: Box::new(after) });
+    }
+
+    #[allow(dead_code)]
+    pub fn add_child(&mut self, scene: &mut Scene, parent: NodeId, node: SceneNode) -> NodeId {
+        let id = scene.add_child(parent, node);
+        self.record(scene, "add node", Command::Move { id, before: None, after: placement(scene, id) });
+        id
+    }
+
+    // Detaches the node and its subtree, to be freed when the removal can no longer be undone
+    pub fn remove(&mut self, scene: &mut Scene, id: NodeId) {
+        assert!(id != scene.root(), "The root cannot be removed");
+        let before = placement(scene, id);
+        scene.detach(id, false);
+        self.record(scene, "remove node", Command::Move { id, before, after: None });
+    }
+
+    // See `Scene::reparent`
+    #[allow(dead_code)]
+    pub fn reparent(&mut self, scene: &mut Scene, id: NodeId, new_parent: NodeId, keep_world_transform: bool) {
+        let before = placement(scene, id);
+        let state_before = NodeState::capture(&scene[id]);
+        scene.reparent(id, new_parent, keep_world_transform);
+
+        // Keeping the world transform changes the local one, which is undone separately
+        self.begin_group("reparent node");
+        self.record(scene, "reparent node", Command::Move { id, before, after: placement(scene, id) });
+        if keep_world_transform {
+            let after = NodeState::capture(&scene[id]);
+            self.record(scene, "reparent node", Command::Edit { id, before: Box::new(state_before), after: Box::new(after) });
+        }
+        self.end_group(scene);
+    }
+
+    // Until the matching `end_group`, everything recorded is undone and redone as one step
+    pub fn begin_group(&mut self, label: &str) {
+        if self.group_depth == 0 {
+            self.group = Some(Entry { label: label.to_string(), commands: vec![], sealed: true });
+        }
+        self.group_depth += 1;
+    }
+
+    pub fn end_group(&mut self, scene: &mut Scene) {
+        assert!(self.group_depth > 0, "end_group without begin_group");
+        self.group_depth -= 1;
+        if self.group_depth == 0 {
+            let group = self.group.take().expect("An open group");
+            if !group.commands.is_empty() {
+                self.push(scene, group);
+            }
+        }
+    }
+
+    // Keeps the next edit from being merged into the last one
+    pub fn seal(&mut self) {
+        if let Some(entry) = self.undo_stack.last_mut() {
+            entry.sealed = true;
+        }
+    }
+
+    // Returns the label of what was undone, if there was anything to undo
+    pub fn undo(&mut self, scene: &mut Scene) -> Option<String> {
+        assert!(self.group.is_none(), "Cannot undo while a group is open");
+        let mut entry = self.undo_stack.pop()?;
+        for command in entry.commands.iter().rev() {
+            command.undo(scene);
+        }
+        entry.sealed = true;
+        let label = entry.label.clone();
+        self.redo_stack.push(entry);
+        Some(label)
+    }
+
+    // Returns the label of what was redone, if there was anything to redo
+    pub fn redo(&mut self, scene: &mut Scene) -> Option<String> {
+        assert!(self.group.is_none(), "Cannot redo while a group is open");
+        let entry = self.redo_stack.pop()?;
+        for command in &entry.commands {
+            command.redo(scene);
+        }
+        let label = entry.label.clone();
+        self.undo_stack.push(entry);
+        Some(label)
+    }
+
+    #[allow(dead_code)]
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    #[allow(dead_code)]
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    fn record(&mut self, scene: &mut Scene, label: &str, command: Command) {
+        match &mut self.group {
+            Some(group) => group.commands.push(command),
+            None => self.push(scene, Entry { label: label.to_string(), commands: vec![command], sealed: false }),
+        }
+    }
+
+    fn push(&mut self, scene: &mut Scene, entry: Entry) {
+        self.clear_redo(scene);
+        if let Some(previous) = self.undo_stack.last_mut() {
+            previous.sealed = true;
+        }
+        self.undo_stack.push(entry);
+        while self.undo_stack.len() > self.limit {
+            let oldest = self.undo_stack.remove(0);
+            self.forget(scene, oldest);
+        }
+    }
+
+    // A new edit makes the undone ones unreachable
+    fn clear_redo(&mut self, scene: &mut Scene) {
+        for entry in std::mem::take(&mut self.redo_stack) {
+            self.forget(scene, entry);
+        }
+    }
+
+    // Frees the nodes the entry left detached, unless the history still refers to them
+    fn forget(&mut self, scene: &mut Scene, entry: Entry) {
+        for command in entry.commands {
+            let Command::Move { id, .. } = command else { continue };
+            if !scene.contains(id) || scene.parent(id).is_some() || id == scene.root() {
+                continue;
+            }
+            let subtree: Vec<NodeId> = scene.depth_first(id).map(|visit| visit.id).collect();
+            let referenced = self.entries()
+                .flat_map(|entry| entry.commands.iter())
+                .any(|command| command.refers_to(&subtree));
+            if !referenced {
+                scene.remove(id);
+            }
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.undo_stack.iter().chain(self.redo_stack.iter()).chain(self.group.iter())
+    }
+}
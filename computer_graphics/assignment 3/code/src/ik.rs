@@ -0,0 +1,208 @@
+extern crate nalgebra_glm as glm;
+
+use crate::behaviour::{Behaviour, FrameTime};
+use crate::rotation;
+use crate::scene_graph::{NodeId, Scene, SceneNode};
+
+// Inverse kinematics: turning the joints of an articulated chain, like a winch arm or a landing
+// gear leg, so that its tip reaches a point in the world. A chain is a line of nodes from a base
+// joint down to an end effector, each the parent of the next. Every joint turns about its
+// reference point by changing its rotation, the positions are left alone, so the links between
+// the joints keep their lengths.
+//
+// Two solvers, both iterating until the tip is within `tolerance` of the target:
+//  * CCD (cyclic coordinate descent) turns one joint at a time, from the tip up, to point the tip
+//    at the target. Cheap and simple, but it tends to curl the joints nearest the tip.
+//  * FABRIK (forward and backward reaching) moves the joint positions instead, dragging the chain
+//    from the target to the base and back, then turns every joint to match. It converges in
+//    fewer iterations and spreads the bend more evenly along the chain.
+//
+// Joints can be limited to ranges of Euler angles in their own rotation order, which is also how
+// a hinge is made: a range of zero on two of the axes. The solvers clamp every joint after
+// turning it, so a limited chain may stop short of a target it could otherwise reach.
+// Scaling in the chain has to be uniform, or the turned links would also be stretched.
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum IkSolver {
+    Ccd,
+    Fabrik,
+}
+
+// The smallest and largest angles around X, Y and Z, in radians between -PI and PI
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct JointLimit {
+    pub min : glm::Vec3,
+    pub max : glm::Vec3,
+}
+
+impl JointLimit {
+    // Only turning around `axis` (0 = X, 1 = Y, 2 = Z), between `min` and `max`
+    #[allow(dead_code)]
+    pub fn hinge(axis: usize, min: f32, max: f32) -> JointLimit {
+        let mut limit = JointLimit { min: glm::zero(), max: glm::zero() };
+        limit.min[axis] = min;
+        limit.max[axis] = max;
+        limit
+    }
+
+    fn apply(&self, node: &mut SceneNode) {
+        let angles = rotation::quat_to_euler(&node.rotation_quat(), node.rotation_order);
+        let clamped = glm::clamp_vec(&angles, &self.min, &self.max);
+        node.set_rotation_quat(&rotation::euler_to_quat(&clamped, node.rotation_order));
+    }
+}
+
+pub struct IkJoint {
+    pub node  : NodeId,
+    pub limit : Option<JointLimit>,
+}
+
+pub struct IkChain {
+    pub joints     : Vec<IkJoint>,  // From the base down, each the parent of the next
+    pub effector   : NodeId,        // Its origin is the tip that reaches for the target
+    pub iterations : usize,         // At most, per solve
+    pub tolerance  : f32,           // How close to the target is close enough, in world units
+}
+
+impl IkChain {
+    // The chain from `base` down to `effector`, turning every node on the way except the effector
+    pub fn new(scene: &Scene, base: NodeId, effector: NodeId) -> IkChain {
+        assert!(base != effector && scene.is_ancestor(base, effector), "The end effector has to be below the base of the chain");
+        let mut joints = vec![];
+        let mut current = scene.parent(effector);
+        while let Some(node) = current {
+            joints.push(IkJoint { node, limit: None });
+            if node == base {
+                break;
+            }
+            current = scene.parent(node);
+        }
+        joints.reverse();
+        IkChain { joints, effector, iterations: 20, tolerance: 0.01 }
+    }
+
+    pub fn with_limit(mut self, joint: NodeId, limit: JointLimit) -> IkChain {
+        let joint = self.joints.iter_mut().find(|j| j.node == joint).expect("The node is not a joint of the chain");
+        joint.limit = Some(limit);
+        self
+    }
+
+    // Turns the joints for the tip to reach `target`, given in world space. Returns how far from
+    // it the tip ended up, as close as the chain's length and limits allow.
+    pub fn solve(&self, scene: &mut Scene, target: &glm::Vec3, solver: IkSolver) -> f32 {
+        for _ in 0..self.iterations {
+            if self.distance(scene, target) <= self.tolerance {
+                break;
+            }
+            match solver {
+                IkSolver::Ccd => self.ccd_iteration(scene, target),
+                IkSolver::Fabrik => self.fabrik_iteration(scene, target),
+            }
+        }
+        self.distance(scene, target)
+    }
+
+    fn distance(&self, scene: &Scene, target: &glm::Vec3) -> f32 {
+        glm::distance(&origin(scene, self.effector), target)
+    }
+
+    fn ccd_iteration(&self, scene: &mut Scene, target: &glm::Vec3) {
+        for joint in self.joints.iter().rev() {
+            let tip = origin(scene, self.effector);
+            aim(scene, joint, &tip, target);
+        }
+    }
+
+    fn fabrik_iteration(&self, scene: &mut Scene, target: &glm::Vec3) {
+        // The pivots of the joints and the tip, and the lengths of the links between them
+        let mut points: Vec<glm::Vec3> = self.joints.iter().map(|joint| pivot(scene, joint.node)).collect();
+        points.push(origin(scene, self.effector));
+        let lengths: Vec<f32> = points.windows(2).map(|link| glm::distance(&link[0], &link[1])).collect();
+        let base = points[0];
+
+        // Backward: put the tip on the target and drag the links after it
+        let last = points.len() - 1;
+        points[last] = *target;
+        for i in (0..last).rev() {
+            points[i] = points[i + 1] + direction(&points[i + 1], &points[i]) * lengths[i];
+        }
+        // Forward: put the base back and drag the links after it
+        points[0] = base;
+        for i in 0..last {
+            points[i + 1] = points[i] + direction(&points[i], &points[i + 1]) * lengths[i];
+        }
+
+        // Turn the joints from the base down to point at their new next point. The limits may
+        // keep them from it, which the next iteration starts from.
+        for (i, joint) in self.joints.iter().enumerate() {
+            let next = match self.joints.get(i + 1) {
+                Some(next) => pivot(scene, next.node),
+                None => origin(scene, self.effector),
+            };
+            aim(scene, joint, &next, &points[i + 1]);
+        }
+    }
+}
+
+// Where the node's origin is in the world
+fn origin(scene: &Scene, id: NodeId) -> glm::Vec3 {
+    (scene.world_matrix(id) * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz()
+}
+
+// Where the point the node turns about is in the world
+fn pivot(scene: &Scene, id: NodeId) -> glm::Vec3 {
+    (scene.world_matrix(id) * scene[id].reference_point.push(1.0)).xyz()
+}
+
+// The unit vector from `from` towards `to`, or any unit vector if they are the same point
+fn direction(from: &glm::Vec3, to: &glm::Vec3) -> glm::Vec3 {
+    let offset = to - from;
+    let length = glm::length(&offset);
+    if length > 1e-6 { offset / length } else { glm::vec3(0.0, -1.0, 0.0) }
+}
+
+// Turns the joint about its pivot so that `point`, which it carries along, moves towards `goal`
+// as seen from the pivot. Both are in world space.
+fn aim(scene: &mut Scene, joint: &IkJoint, point: &glm::Vec3, goal: &glm::Vec3) {
+    let pivot = pivot(scene, joint.node);
+    let from = direction(&pivot, point);
+    let to = direction(&pivot, goal);
+    let axis = glm::cross(&from, &to);
+    let sin_angle = glm::length(&axis);
+    if sin_angle < 1e-6 {
+        return; // Already there, or exactly opposite with no preferred way to turn
+    }
+    let angle = sin_angle.atan2(glm::dot(&from, &to));
+
+    // Rotations are in the parent's space, so the axis is taken there
+    let parent_world = match scene.parent(joint.node) {
+        Some(parent) => scene.world_matrix(parent),
+        None => glm::identity(),
+    };
+    let parent_axis = (glm::inverse(&parent_world) * axis.push(0.0)).xyz();
+    let node = &mut scene[joint.node];
+    node.rotate_around(&parent_axis, angle);
+    if let Some(limit) = &joint.limit {
+        limit.apply(node);
+    }
+}
+
+// Keeps a chain reaching for a target node every frame, attached to any node (e.g. the chain's
+// base). Each frame starts from where the last one left off, so the chain follows a moving target
+// smoothly and a few iterations per frame are enough.
+pub struct Reach {
+    pub chain  : IkChain,
+    pub target : NodeId,
+    pub solver : IkSolver,
+}
+
+impl Behaviour for Reach {
+    fn update(&mut self, scene: &mut Scene, _id: NodeId, _time: &FrameTime) {
+        let nodes = self.chain.joints.iter().map(|joint| joint.node).chain([self.chain.effector, self.target]);
+        if nodes.into_iter().all(|node| scene.contains(node)) {
+            let target = origin(scene, self.target);
+            self.chain.solve(scene, &target, self.solver);
+        }
+    }
+}
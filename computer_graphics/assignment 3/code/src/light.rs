@@ -0,0 +1,96 @@
+extern crate nalgebra_glm as glm;
+
+use std::ffi::CString;
+
+use serde::{Deserialize, Serialize};
+
+use crate::scene_graph::{NodeId, Scene};
+
+// Lights are nodes too: a node with a `Light` shines from its world space origin along its own -Z
+// axis, so a searchlight mounted on a helicopter follows it around, and a light is aimed like any
+// other node (e.g. with `SceneNode::look_at`).
+//
+// Every frame the visible lights are collected from the scene with their world positions and
+// directions (`Scene::lights`) and handed to the shaders as uniforms (`apply_lights`). The
+// shaders take at most `MAX_LIGHTS`, the lights after those are left out.
+
+// Has to match MAX_LIGHTS in simple.frag
+pub const MAX_LIGHTS: usize = 8;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum LightKind {
+    // From infinitely far away, like the sun, the position does not matter
+    Directional,
+    // In every direction, fading out to nothing `range` away
+    Point { range: f32 },
+    // A cone, full strength up to `inner_angle` from its axis and fading out to `outer_angle`
+    // (in radians), and with distance like a point light
+    Spot { range: f32, inner_angle: f32, outer_angle: f32 },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Light {
+    pub kind      : LightKind,
+    pub color     : glm::Vec3,
+    pub intensity : f32,  // Multiplies the color
+}
+
+impl Light {
+    pub fn new(kind: LightKind, color: glm::Vec3, intensity: f32) -> Light {
+        Light { kind, color, intensity }
+    }
+}
+
+// A light where it is in the world this frame
+pub struct WorldLight {
+    pub light     : Light,
+    pub position  : glm::Vec3,
+    pub direction : glm::Vec3,  // Unit length
+}
+
+impl Scene {
+    // The lights in the subtree below `start` that are visible along with all their ancestors
+    pub fn lights(&self, start: NodeId) -> Vec<WorldLight> {
+        self.depth_first(start)
+            .filter(|visit| visit.node.light.is_some() && self.is_visible(visit.id))
+            .map(|visit| {
+                let direction = visit.world_matrix * glm::vec4(0.0, 0.0, -1.0, 0.0);
+                WorldLight {
+                    light: visit.node.light.unwrap(),
+                    position: (visit.world_matrix * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz(),
+                    direction: glm::normalize(&direction.xyz()),
+                }
+            })
+            .collect()
+    }
+}
+
+// Sets the `lights` and `light_count` uniforms of `program_id`, which must be in use. Programs
+// without them (or with them optimized away) are left alone.
+pub unsafe fn apply_lights(lights: &[WorldLight], program_id: u32) {
+    let location = |name: &str| {
+        let name_cstr = CString::new(name).expect("CString::new failed");
+        gl::GetUniformLocation(program_id, name_cstr.as_ptr())
+    };
+
+    let count = lights.len().min(MAX_LIGHTS);
+    gl::Uniform1i(location("light_count"), count as i32);
+    for (i, world_light) in lights[..count].iter().enumerate() {
+        let light = &world_light.light;
+        // The shader compares the cosines of the angles, which is cheaper than the angles
+        let (kind, range, cos_inner, cos_outer) = match light.kind {
+            LightKind::Directional => (0, 0.0, 0.0, 0.0),
+            LightKind::Point { range } => (1, range, 0.0, 0.0),
+            LightKind::Spot { range, inner_angle, outer_angle } => (2, range, inner_angle.cos(), outer_angle.cos()),
+        };
+        let color = light.color * light.intensity;
+        gl::Uniform1i(location(&format!("lights[{}].kind", i)), kind);
+        gl::Uniform3fv(location(&format!("lights[{}].position", i)), 1, world_light.position.as_ptr());
+        gl::Uniform3fv(location(&format!("lights[{}].direction", i)), 1, world_light.direction.as_ptr());
+        gl::Uniform3fv(location(&format!("lights[{}].color", i)), 1, color.as_ptr());
+        gl::Uniform1f(location(&format!("lights[{}].range", i)), range);
+        gl::Uniform1f(location(&format!("lights[{}].cos_inner", i)), cos_inner);
+        gl::Uniform1f(location(&format!("lights[{}].cos_outer", i)), cos_outer);
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs::File;
+use std::any::Any;
+use std::io::{self, BufRead, BufReader, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+use crate::ambient_occlusion::AmbientOcclusion;
+use crate::mesh::{self, Helicopter, Mesh, Terrain};
+
+// Parses OBJ files on worker threads so the render thread can keep drawing (e.g. a loading screen)
+// while large models load. Parsing and building the `Mesh`es is all CPU work and happens on the
+// workers; the finished meshes are handed back through `poll`, which is meant to be called from the
+// GL thread, since that is the only thread allowed to turn them into VAOs.
+//
+// Every job reports back through its own channel. A worker that fails, even by panicking, sends
+// the error instead of an asset, and one that dies without sending anything is noticed by its
+// channel closing, so `is_done` always becomes true eventually.
+
+// What a finished job hands back to the GL thread
+pub enum LoadedAsset {
+    Terrain(Mesh),
+    Helicopter(Box<Helicopter>),
+}
+
+#[derive(Clone, Copy)]
+enum AssetKind {
+    Terrain(Option<AmbientOcclusion>),
+    Helicopter,
+}
+
+struct Job {
+    path        : PathBuf,
+    bytes_total : u64,
+    bytes_read  : Arc<AtomicU64>,  // Bumped by the worker as it parses the file
+    receiver    : Receiver<Result<LoadedAsset, String>>,  // The worker holds the only sender
+    done        : bool,
+}
+
+pub struct AssetLoader {
+    jobs              : Vec<Job>,
+    terrain_occlusion : Option<AmbientOcclusion>,  // Baked into terrains after parsing, if set
+}
+
+impl AssetLoader {
+    pub fn new() -> AssetLoader {
+        AssetLoader {
+            jobs: vec![],
+            terrain_occlusion: None,
+        }
+    }
+
+    // Bake ambient occlusion into the vertex colors of terrains queued from now on. The bake runs
+    // on the worker thread as well, after the file has been parsed.
+    pub fn bake_terrain_occlusion(&mut self, settings: AmbientOcclusion) {
+        self.terrain_occlusion = Some(settings);
+    }
+
+    // Start parsing a single mesh terrain model, returns the id `poll` will report it under
+    pub fn load_terrain(&mut self, path: &str) -> usize {
+        self.spawn(path, AssetKind::Terrain(self.terrain_occlusion))
+    }
+
+    // Start parsing a helicopter model, returns the id `poll` will report it under
+    pub fn load_helicopter(&mut self, path: &str) -> usize {
+        self.spawn(path, AssetKind::Helicopter)
+    }
+
+    fn spawn(&mut self, path: &str, kind: AssetKind) -> usize {
+        let id = self.jobs.len();
+        let path = PathBuf::from(path);
+        // A missing file is reported by the worker, so just count it as empty here
+        let bytes_total = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let bytes_read = Arc::new(AtomicU64::new(0));
+
+        let counter = Arc::clone(&bytes_read);
+        let worker_path = path.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // A panic while building the meshes is reported like any other failure
+            let result = panic::catch_unwind(AssertUnwindSafe(|| parse_asset(&worker_path, kind, counter)))
+                .unwrap_or_else(|payload| Err(format!("Failed to load {}: {}", worker_path.display(), panic_message(&*payload))));
+            // The loader may have been dropped in the meantime, nobody is waiting for us then
+            let _ = sender.send(result);
+        });
+
+        self.jobs.push(Job { path, bytes_total, bytes_read, receiver, done: false });
+        id
+    }
+
+    // Fraction in [0, 1] of the queued bytes that have been parsed so far
+    pub fn progress(&self) -> f32 {
+        let total: u64 = self.jobs.iter().map(|job| job.bytes_total).sum();
+        if total == 0 {
+            return if self.is_done() { 1.0 } else { 0.0 };
+        }
+        let read: u64 = self.jobs.iter()
+            .map(|job| if job.done { job.bytes_total } else { job.bytes_read.load(Ordering::Relaxed) })
+            .sum();
+        (read as f32 / total as f32).min(1.0)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.jobs.iter().all(|job| job.done)
+    }
+
+    // Collect the jobs that finished since the last call without blocking, with either their
+    // asset or why it could not be loaded
+    pub fn poll(&mut self) -> Vec<(usize, Result<LoadedAsset, String>)> {
+        let mut finished = vec![];
+        for (id, job) in self.jobs.iter_mut().enumerate().filter(|(_, job)| !job.done) {
+            let result = match job.receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => continue,
+                Err(TryRecvError::Disconnected) => {
+                    Err(format!("The worker loading {} stopped without a result", job.path.display()))
+                }
+            };
+            job.done = true;
+            finished.push((id, result));
+        }
+        finished
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("unknown panic", String::as_str),
+    }
+}
+
+// Runs on a worker thread
+fn parse_asset(path: &Path, kind: AssetKind, bytes_read: Arc<AtomicU64>) -> Result<LoadedAsset, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut reader = ProgressReader {
+        inner: BufReader::new(file),
+        bytes_read,
+    };
+
+    let before = std::time::Instant::now();
+    // Material libraries are resolved relative to the OBJ file, like `tobj::load_obj` does
+    let directory = path.parent().unwrap_or(Path::new("")).to_owned();
+    let (models, _materials) = tobj::load_obj_buf(
+        &mut reader,
+        &mesh::load_options(),
+        |mtl_path| tobj::load_mtl(directory.join(mtl_path)),
+    ).map_err(|e| format!("Failed to load model {}: {}", path.display(), e))?;
+    let after = std::time::Instant::now();
+    println!("Parsed {} in {:.3}ms.", path.display(), after.duration_since(before).as_micros() as f32 / 1e3);
+
+    let file = path.to_string_lossy();
+    Ok(match kind {
+        AssetKind::Terrain(occlusion) => {
+            let mut terrain = Terrain::from_models(&file, models)?;
+            if let Some(occlusion) = occlusion {
+                let before = std::time::Instant::now();
+                occlusion.bake_into_colors(&mut terrain);
+                let after = std::time::Instant::now();
+                println!("Baked ambient occlusion for {} in {:.3}ms.", path.display(), after.duration_since(before).as_micros() as f32 / 1e3);
+            }
+            LoadedAsset::Terrain(terrain)
+        }
+        AssetKind::Helicopter => LoadedAsset::Helicopter(Box::new(Helicopter::from_models(&file, models)?)),
+    })
+}
+
+// Counts how much of the file the parser has consumed, for progress reporting
+struct ProgressReader<R> {
+    inner      : R,
+    bytes_read : Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for ProgressReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.bytes_read.fetch_add(amt as u64, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,106 @@
+extern crate nalgebra_glm as glm;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::geometry::Aabb;
+use crate::gpu_mesh::GpuMesh;
+
+// Levels of detail: a node with a `Lod` draws one of several versions of a mesh, from the full
+// mesh up close to cheaper ones (see `simplify`) further away. Which one is picked by the size
+// the node has on screen, measured either as the distance from the camera to its bounds or as
+// the fraction of the screen's height it covers.
+//
+// Near a threshold a node would flip back and forth between two levels as it moves a tiny bit, so
+// every threshold is a band `hysteresis` wide (relative to the threshold): a node only switches
+// to the coarser level past the far side of the band and back to the finer one past the near side.
+// The level shown last is remembered on the node for that.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LodMetric {
+    Distance,    // World units from the camera to the closest point of the bounds
+    ScreenSize,  // The height of the bounding sphere on screen, as a fraction of the screen's height
+}
+
+pub struct LodLevel {
+    pub mesh      : Rc<GpuMesh>,
+    pub threshold : f32,  // The distance up to or screen size down to which it is drawn, unused on the last level
+}
+
+pub struct Lod {
+    pub levels     : Vec<LodLevel>,  // The finest first
+    pub metric     : LodMetric,
+    pub hysteresis : f32,
+    current        : Cell<usize>,    // The level picked last time
+}
+
+impl Lod {
+    pub fn new(levels: Vec<LodLevel>, metric: LodMetric, hysteresis: f32) -> Lod {
+        assert!(!levels.is_empty(), "A level of detail needs at least one level");
+        Lod { levels, metric, hysteresis, current: Cell::new(0) }
+    }
+
+    // The finest mesh, which stands in for the node wherever one mesh is needed, e.g. for bounds
+    pub fn finest(&self) -> &Rc<GpuMesh> {
+        &self.levels[0].mesh
+    }
+
+    pub fn current_level(&self) -> usize {
+        self.current.get()
+    }
+
+    // Picks the level to draw for world space `bounds`, seen by a camera at `camera_position`
+    // drawing with `view_projection_matrix`
+    pub fn select(&self, bounds: &Aabb, camera_position: &glm::Vec3, view_projection_matrix: &glm::Mat4) -> &Rc<GpuMesh> {
+        // Larger values want coarser levels with both metrics, so screen sizes are negated
+        let value = match self.metric {
+            LodMetric::Distance => {
+                let closest = glm::clamp_vec(camera_position, &bounds.min, &bounds.max);
+                glm::distance(&closest, camera_position)
+            }
+            LodMetric::ScreenSize => -screen_size(bounds, view_projection_matrix),
+        };
+        let boundary = |level: usize| {
+            let threshold = self.levels[level].threshold;
+            let value = if self.metric == LodMetric::ScreenSize { -threshold } else { threshold };
+            (value, self.hysteresis * threshold.abs())
+        };
+
+        let mut level = self.current.get().min(self.levels.len() - 1);
+        while level + 1 < self.levels.len() {
+            let (threshold, band) = boundary(level);
+            if value <= threshold + band {
+                break;
+            }
+            level += 1;
+        }
+        while level > 0 {
+            let (threshold, band) = boundary(level - 1);
+            if value >= threshold - band {
+                break;
+            }
+            level -= 1;
+        }
+        self.current.set(level);
+        &self.levels[level].mesh
+    }
+}
+
+// The height of the sphere around `bounds` on screen, as a fraction of the screen's height. The
+// clip space y of a point grows with the projection's vertical scale over its depth w.
+fn screen_size(bounds: &Aabb, view_projection_matrix: &glm::Mat4) -> f32 {
+    let radius = glm::length(&bounds.extent()) * 0.5;
+    let w = glm::dot(&view_projection_matrix.row(3).transpose(), &bounds.center().push(1.0));
+    if w <= 0.0 {
+        return f32::INFINITY; // The camera is inside the sphere, or the center is behind it
+    }
+    let vertical_scale = glm::length(&view_projection_matrix.row(1).transpose().xyz());
+    radius * vertical_scale / w
+}
+
+// Where the camera drawing with a perspective `view_projection_matrix` is, in world space: the
+// point the matrix sends to w = 0 with x = y = 0
+pub fn camera_position(view_projection_matrix: &glm::Mat4) -> glm::Vec3 {
+    let point = glm::inverse(view_projection_matrix) * glm::vec4(0.0, 0.0, 1.0, 0.0);
+    point.xyz() / point.w
+}
@@ -8,25 +8,54 @@
 #![allow(unused_variables)]
 */
 extern crate nalgebra_glm as glm;
+use std::collections::HashMap;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 use std::{mem, os::raw::c_void, ptr};
 
+mod ambient_occlusion;
+mod behaviour;
+mod camera;
+mod collision;
+mod curvature;
+mod edges;
+mod geometry;
+mod gpu_mesh;
+mod history;
+mod ik;
+mod light;
+mod loader;
+mod lod;
+mod marching_cubes;
+mod material;
 mod mesh;
+mod node_path;
+mod picking;
+mod rotation;
+mod scene_bvh;
+mod scene_debug;
+mod scene_file;
 mod scene_graph;
 mod shader;
+mod simplify;
 mod toolbox;
+mod traversal;
 mod util;
 
 use glutin::event::{
     DeviceEvent,
     ElementState::{Pressed, Released},
-    Event, KeyboardInput,
+    Event, KeyboardInput, MouseButton,
     VirtualKeyCode::{self, *},
     WindowEvent,
 };
 use glutin::event_loop::ControlFlow;
-use scene_graph::SceneNode;
+use loader::LoadedAsset;
+use gpu_mesh::{GpuMesh, Instance};
+use material::Material;
+use scene_graph::{NodeId, SceneNode};
+use traversal::{SceneVisitor, Visit};
 
 // initial window size
 const INITIAL_SCREEN_W: u32 = 800;
@@ -172,162 +201,281 @@ unsafe fn create_vao(
     return vao_id;
 }
 
+// Render layers of the scene, see `SceneNode::layers`
+const TERRAIN_LAYER: u32 = 1 << 1;
+const HELICOPTER_LAYER: u32 = 1 << 2;
+
 /*********************************************************************/
-/* Task 2c and 3 - Recursive draw_scene function */
+/* Task 2c and 3 - draw_scene, as a visitor over the scene graph */
 /*********************************************************************/
+// Collects the nodes drawing the same mesh with the same shader and uniforms into one batch, so
+// they are drawn with one instanced draw call instead of one call per node
+struct SceneRenderer {
+    layer_mask: u32,
+    frustum: geometry::Frustum, // What the camera sees, in world space
+    camera_position: glm::Vec3,
+    view_projection_matrix: glm::Mat4,
+    materials: Vec<Material>, // The material in effect at every level from the start down
+    batches: Vec<Batch>,
+    stats: RenderStats,
+}
+
+// How much of the scene one `draw_scene` call drew
+#[derive(Default, Clone, Copy)]
+struct RenderStats {
+    drawn: usize,      // Nodes with a mesh that were drawn
+    culled: usize,     // Nodes with a mesh that were outside the view frustum
+    draw_calls: usize, // One per batch of nodes sharing a mesh
+}
+
+struct Batch {
+    mesh: Rc<GpuMesh>,
+    material: Material, // Only the shader and the uniforms matter, the rest is per instance
+    instances: Vec<Instance>,
+}
+
+impl SceneVisitor for SceneRenderer {
+    fn enter(&mut self, scene: &scene_graph::Scene, visit: &Visit) -> bool {
+        // Materials are inherited unless the node overrides them
+        let inherited = self.materials.last().cloned().unwrap_or_default();
+        let material = match &visit.node.material {
+            Some(material) => material.inherit(&inherited),
+            None => inherited,
+        };
+
+        // Hidden nodes hide their whole subtree
+        let visible = visit.node.visible;
+
+        // So do subtrees entirely outside the view, e.g. a whole helicopter behind the camera is
+        // skipped after testing one box. `leave` is still called, so the material goes on the stack.
+        if visible && !self.frustum.intersects_aabb(&scene.subtree_bounds(visit.id)) {
+            self.stats.culled += scene.subtree_mesh_count(visit.id);
+            self.materials.push(material);
+            return false;
+        }
+
+        // The traversal hands us the world matrix from the scene's cache, so only nodes that
+        // moved since the last frame are recomputed. Layers only apply to the node itself.
+        let in_layers = visit.node.layers & self.layer_mask != 0;
+        if let Some(primary_mesh) = visit.node.primary_mesh().filter(|_| visible && in_layers) {
+            // The subtree is in view, but the node's own mesh may still not be
+            let bounds = primary_mesh.bounds.transformed(&visit.world_matrix);
+            if !self.frustum.intersects_aabb(&bounds) {
+                self.stats.culled += 1;
+                self.materials.push(material);
+                return visible;
+            }
+            self.stats.drawn += 1;
+
+            // With levels of detail, the one for how large the node is on screen
+            let mesh = match &visit.node.lod {
+                Some(lod) => lod.select(&bounds, &self.camera_position, &self.view_projection_matrix),
+                None => primary_mesh,
+            };
+
+            let instance = Instance {
+                model_matrix: visit.world_matrix,
+                tint: material.tint_or_white(),
+                emissive: material.emissive_or_black(),
+            };
+            let batch = self.batches.iter_mut().find(|batch| {
+                Rc::ptr_eq(&batch.mesh, mesh)
+                    && batch.material.shader == material.shader
+                    && batch.material.uniforms == material.uniforms
+            });
+            match batch {
+                Some(batch) => batch.instances.push(instance),
+                None => self.batches.push(Batch {
+                    mesh: Rc::clone(mesh),
+                    material: material.clone(),
+                    instances: vec![instance],
+                }),
+            }
+        }
+
+        self.materials.push(material);
+        visible
+    }
+
+    fn leave(&mut self, _scene: &scene_graph::Scene, _visit: &Visit) {
+        self.materials.pop();
+    }
+}
+
+// Draws the visible nodes below `node_id` that are in any of the layers in `layer_mask`, with
+// `default_shader` unless their material says otherwise, lit by `lights`. Nodes outside the view
+// frustum are culled, by their meshes' bounds cached in the scene.
 unsafe fn draw_scene(
-    node: &scene_graph::SceneNode,
+    scene: &scene_graph::Scene,
+    node_id: NodeId,
     view_projection_matrix: &glm::Mat4,
-    transformation_so_far: &glm::Mat4,
-) {
-    // Perform any logic needed before drawing the node
-    let mut model_matrix = glm::identity();
-
-    model_matrix = glm::translate(&model_matrix, &node.position);
-    model_matrix = glm::translate(&model_matrix, &node.reference_point);
-    model_matrix = glm::scale(&model_matrix, &node.scale);
-    model_matrix = glm::rotate_x(&model_matrix, node.rotation.x);
-    model_matrix = glm::rotate_y(&model_matrix, node.rotation.y);
-    model_matrix = glm::rotate_z(&model_matrix, node.rotation.z);
-    model_matrix = glm::translate(&model_matrix, &-node.reference_point);
-    model_matrix = transformation_so_far * model_matrix;
-
-    let mvp_matrix: glm::Mat4 = view_projection_matrix * model_matrix;
-
-    // Check if node is drawable, if so: set uniforms, bind VAO and draw VAO
-    if node.index_count > 0 {
-        gl::BindVertexArray(node.vao_id);
-
-        gl::UniformMatrix4fv(0, 1, gl::FALSE, mvp_matrix.as_ptr());
-        gl::UniformMatrix4fv(1, 1, gl::FALSE, model_matrix.as_ptr());
-
-        gl::DrawElements(
-            gl::TRIANGLES,
-            node.index_count,
-            gl::UNSIGNED_INT,
-            ptr::null(),
-        );
+    lights: &[light::WorldLight],
+    layer_mask: u32,
+    default_shader: &shader::Shader,
+) -> RenderStats {
+    let mut renderer = SceneRenderer {
+        layer_mask,
+        frustum: geometry::Frustum::from_matrix(view_projection_matrix),
+        camera_position: lod::camera_position(view_projection_matrix),
+        view_projection_matrix: *view_projection_matrix,
+        materials: vec![],
+        batches: vec![],
+        stats: RenderStats::default(),
+    };
+    scene.walk(node_id, &mut renderer);
+
+    // The model matrices come per instance, see `GpuMesh`. The lights are the same for every
+    // batch, and stay set in a program once it has them.
+    let mut lit_programs: Vec<u32> = vec![];
+    for batch in &renderer.batches {
+        let program_id = batch.material.shader.unwrap_or(default_shader.program_id);
+        gl::UseProgram(program_id);
+        gl::UniformMatrix4fv(0, 1, gl::FALSE, view_projection_matrix.as_ptr());
+        if !lit_programs.contains(&program_id) {
+            light::apply_lights(lights, program_id);
+            lit_programs.push(program_id);
+        }
+        batch.material.apply_uniforms(program_id);
+        batch.mesh.draw_instanced(&batch.instances);
     }
+    default_shader.activate();
 
-    // Recurse
-    for &child in &node.children {
-        draw_scene(&*child, view_projection_matrix, &model_matrix);
-    }
+    renderer.stats.draw_calls = renderer.batches.len();
+    renderer.stats
+}
+
+/*********************************************************************/
+/* Loading screen shown while the models are parsed */
+/*********************************************************************/
+unsafe fn draw_loading_screen(progress: f32, screen_w: u32, screen_h: u32) {
+    gl::ClearColor(0.035, 0.046, 0.078, 1.0); // night sky, full opacity
+    gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+    // The progress bar is drawn with scissored clears, so no shaders or VAOs are needed yet
+    let bar_w = (screen_w as f32 * 0.6) as i32;
+    let bar_h = 20;
+    let bar_x = (screen_w as i32 - bar_w) / 2;
+    let bar_y = (screen_h as i32 - bar_h) / 2;
+
+    gl::Enable(gl::SCISSOR_TEST);
+    gl::Scissor(bar_x, bar_y, bar_w, bar_h);
+    gl::ClearColor(0.15, 0.15, 0.2, 1.0);
+    gl::Clear(gl::COLOR_BUFFER_BIT);
+    gl::Scissor(bar_x, bar_y, (bar_w as f32 * progress.clamp(0.0, 1.0)) as i32, bar_h);
+    gl::ClearColor(0.8, 0.8, 0.85, 1.0);
+    gl::Clear(gl::COLOR_BUFFER_BIT);
+    gl::Disable(gl::SCISSOR_TEST);
 }
 
 /*********************************************************************/
 /* Task 2b, 4 and 6 - Helicopter struct & implementation */
 /*********************************************************************/
+// The helicopter's parts uploaded to the GPU once, and shared by every helicopter
+struct HelicopterMeshes {
+    body: [Rc<GpuMesh>; 3], // The full mesh and two simplified ones, see `lod`
+    door: Rc<GpuMesh>,
+    main_rotor: Rc<GpuMesh>,
+    tail_rotor: Rc<GpuMesh>,
+}
+
+impl HelicopterMeshes {
+    unsafe fn upload(helicopter_object_file: &mesh::Helicopter) -> Self {
+        HelicopterMeshes {
+            body: [
+                GpuMesh::upload(&helicopter_object_file.body),
+                GpuMesh::upload(&simplify::vertex_clustering(&helicopter_object_file.body, 24)),
+                GpuMesh::upload(&simplify::vertex_clustering(&helicopter_object_file.body, 10)),
+            ],
+            door: GpuMesh::upload(&helicopter_object_file.door),
+            main_rotor: GpuMesh::upload(&helicopter_object_file.main_rotor),
+            tail_rotor: GpuMesh::upload(&helicopter_object_file.tail_rotor),
+        }
+    }
+}
+
+#[allow(dead_code)]
 struct Helicopter {
-    helicopter_node: mem::ManuallyDrop<std::pin::Pin<std::boxed::Box<scene_graph::SceneNode>>>,
-    body_node: mem::ManuallyDrop<std::pin::Pin<std::boxed::Box<scene_graph::SceneNode>>>,
-    door_node: mem::ManuallyDrop<std::pin::Pin<std::boxed::Box<scene_graph::SceneNode>>>,
-    main_rotor_node: mem::ManuallyDrop<std::pin::Pin<std::boxed::Box<scene_graph::SceneNode>>>,
-    tail_rotor_node: mem::ManuallyDrop<std::pin::Pin<std::boxed::Box<scene_graph::SceneNode>>>,
+    helicopter_node: NodeId,
+    body_node: NodeId,
+    door_node: NodeId,
+    main_rotor_node: NodeId,
+    tail_rotor_node: NodeId,
 }
 
 impl Helicopter {
-    unsafe fn new(
-        helicopter_object_file: &mesh::Helicopter,
+    fn new(
+        scene: &mut scene_graph::Scene,
+        parent: NodeId,
+        name: &str,
+        meshes: &HelicopterMeshes,
         starting_position: &glm::Vec3,
         starting_rotation: &glm::Vec3,
+        time_offset: f32,
     ) -> Self {
-        // Create VAOs for each body part
-        let helicopter_body_vao = create_vao(
-            &helicopter_object_file.body.vertices,
-            &helicopter_object_file.body.indices,
-            &helicopter_object_file.body.colors,
-            &helicopter_object_file.body.normals,
-        );
-        let helicopter_door_vao = create_vao(
-            &helicopter_object_file.door.vertices,
-            &helicopter_object_file.door.indices,
-            &helicopter_object_file.door.colors,
-            &helicopter_object_file.door.normals,
-        );
-        let helicopter_main_rotor_vao = create_vao(
-            &helicopter_object_file.main_rotor.vertices,
-            &helicopter_object_file.main_rotor.indices,
-            &helicopter_object_file.main_rotor.colors,
-            &helicopter_object_file.main_rotor.normals,
+        // Create the helicopter parent node at its starting position and orientation, flying
+        // the circuit from `time_offset` seconds into it
+        let mut helicopter = SceneNode::new()
+            .with_name(name)
+            .with_behaviour(behaviour::PathFollower {
+                path: toolbox::simple_heading_animation,
+                time: time_offset,
+            });
+        helicopter.position = *starting_position;
+        helicopter.rotation = *starting_rotation;
+        // Yaw, then pitch, then roll, so pitching up never swings the heading around
+        helicopter.rotation_order = rotation::EulerOrder::YXZ;
+        let helicopter_node = scene.add_child(parent, helicopter);
+
+        // Add helicopter body parts (child nodes) to the helicopter node, each rotating and
+        // scaling about its own reference point
+        let body_node = scene.add_child(
+            helicopter_node,
+            // The body has the most triangles, and fewer of them do once it is small on screen
+            SceneNode::from_lod(lod::Lod::new(
+                vec![
+                    lod::LodLevel { mesh: Rc::clone(&meshes.body[0]), threshold: 0.08 },
+                    lod::LodLevel { mesh: Rc::clone(&meshes.body[1]), threshold: 0.03 },
+                    lod::LodLevel { mesh: Rc::clone(&meshes.body[2]), threshold: 0.0 },
+                ],
+                lod::LodMetric::ScreenSize,
+                0.15,
+            ))
+                .with_name("body")
+                .with_layers(HELICOPTER_LAYER)
+                .with_reference_point(glm::vec3(0.00, 0.00, 0.00)),
         );
-        let helicopter_tail_rotor_vao = create_vao(
-            &helicopter_object_file.tail_rotor.vertices,
-            &helicopter_object_file.tail_rotor.indices,
-            &helicopter_object_file.tail_rotor.colors,
-            &helicopter_object_file.tail_rotor.normals,
+        let door_node = scene.add_child(
+            helicopter_node,
+            SceneNode::from_mesh(Rc::clone(&meshes.door))
+                .with_name("door")
+                .with_layers(HELICOPTER_LAYER)
+                .with_reference_point(glm::vec3(0.00, 0.00, 0.00)),
         );
-
-        // Create Scene Graph Nodes
-        let mut helicopter_node = SceneNode::new();
-        let mut body_node =
-            SceneNode::from_vao(helicopter_body_vao, helicopter_object_file.body.index_count);
-        let mut door_node =
-            SceneNode::from_vao(helicopter_door_vao, helicopter_object_file.door.index_count);
-        let mut main_rotor_node = SceneNode::from_vao(
-            helicopter_main_rotor_vao,
-            helicopter_object_file.main_rotor.index_count,
+        let main_rotor_node = scene.add_child(
+            helicopter_node,
+            SceneNode::from_mesh(Rc::clone(&meshes.main_rotor))
+                .with_name("main_rotor")
+                .with_layers(HELICOPTER_LAYER)
+                .with_behaviour(behaviour::Spinner { angular_velocity: glm::vec3(0.0, 8.0, 0.0) })
+                .with_reference_point(glm::vec3(0.00, 0.00, 0.00)),
         );
-        let mut tail_rotor_node = SceneNode::from_vao(
-            helicopter_tail_rotor_vao,
-            helicopter_object_file.tail_rotor.index_count,
+        let tail_rotor_node = scene.add_child(
+            helicopter_node,
+            SceneNode::from_mesh(Rc::clone(&meshes.tail_rotor))
+                .with_name("tail_rotor")
+                .with_layers(HELICOPTER_LAYER)
+                .with_behaviour(behaviour::Spinner { angular_velocity: glm::vec3(8.0, 0.0, 0.0) })
+                .with_reference_point(glm::vec3(0.35, 2.30, 10.40)),
         );
 
-        // Set the reference points for each helicopter body part
-        helicopter_node.reference_point = glm::vec3(0.00, 0.00, 0.00);
-        body_node.reference_point = glm::vec3(0.00, 0.00, 0.00);
-        door_node.reference_point = glm::vec3(0.00, 0.00, 0.00);
-        main_rotor_node.reference_point = glm::vec3(0.00, 0.00, 0.00);
-        tail_rotor_node.reference_point = glm::vec3(0.35, 2.30, 10.40);
-
-        // Set the helicopter starting position and orientation
-        helicopter_node.position = *starting_position;
-        helicopter_node.rotation = *starting_rotation;
-
-        // Add helicopter body parts (child nodes) to a single parent node (body node)
-        let helicopter_body_parts = vec![
-            &mut body_node,
-            &mut door_node,
-            &mut main_rotor_node,
-            &mut tail_rotor_node,
-        ];
-        for helicopter_part in &helicopter_body_parts {
-            helicopter_node.add_child(helicopter_part);
-        }
-
         // Return the helicopter parent and child nodes
-        let mut helicopter = Helicopter {
+        Helicopter {
             helicopter_node,
             body_node,
             door_node,
             main_rotor_node,
             tail_rotor_node,
-        };
-
-        return helicopter;
-    }
-
-    fn spin_rotors(
-        &mut self,
-        main_rotor_orientation: glm::Vec3,
-        tail_rotor_orientation: glm::Vec3,
-    ) {
-        self.main_rotor_node.rotation = main_rotor_orientation;
-        self.tail_rotor_node.rotation = tail_rotor_orientation;
-    }
-
-    fn animate_helicopter(&mut self, elapsed: f32, time_offset: f32) {
-        // Update helicaopter heading
-        let heading: toolbox::Heading = toolbox::simple_heading_animation(elapsed + time_offset);
-        self.helicopter_node.position.x = heading.x;
-        self.helicopter_node.position.z = heading.z;
-        self.helicopter_node.rotation = glm::vec3(heading.pitch, heading.yaw, heading.roll);
-
-        // Spin helicopter rotors
-        self.spin_rotors(
-            glm::Vec3::new(0.0, 8.0 * elapsed, 0.0),
-            glm::Vec3::new(8.0 * elapsed, 0.0, 0.0),
-        );
+        }
     }
 }
 
@@ -360,6 +508,11 @@ fn main() {
     // Make a reference of this tuple to send to the render thread
     let mouse_delta = Arc::clone(&arc_mouse_delta);
 
+    // Set up a shared vector of where the cursor was for every left click since the last frame
+    let arc_mouse_clicks = Arc::new(Mutex::new(Vec::<(f32, f32)>::new()));
+    // Make a reference of this vector to send to the render thread
+    let mouse_clicks = Arc::clone(&arc_mouse_clicks);
+
     // Set up shared tuple for tracking changes to the window size
     let arc_window_size = Arc::new(Mutex::new((INITIAL_SCREEN_W, INITIAL_SCREEN_H, false)));
     // Make a reference of this tuple to send to the render thread
@@ -405,127 +558,319 @@ fn main() {
         /*********************************************************************/
         /* Task 1: Load the Lunar Surface Model and Create a VAO for it */
         /*********************************************************************/
-        let mut lunar_surface: mesh::Mesh = mesh::Terrain::load("./resources/lunarsurface.obj");
-        let mut lunar_surface_vao = unsafe {
-            create_vao(
-                &lunar_surface.vertices,
-                &lunar_surface.indices,
-                &lunar_surface.colors,
-                &lunar_surface.normals,
-            )
+        // The models are parsed on worker threads, show a loading bar until they are all in
+        let mut asset_loader = loader::AssetLoader::new();
+        asset_loader.bake_terrain_occlusion(ambient_occlusion::AmbientOcclusion {
+            samples: 16,
+            max_distance: 20.0,
+            seed: 0,
+        });
+        asset_loader.load_terrain("./resources/lunarsurface.obj");
+        asset_loader.load_helicopter("./resources/helicopter.obj");
+
+        let mut loaded_lunar_surface: Option<mesh::Mesh> = None;
+        let mut loaded_helicopter: Option<mesh::Helicopter> = None;
+        let mut load_failures = 0;
+        while !asset_loader.is_done() {
+            for (_, asset) in asset_loader.poll() {
+                match asset {
+                    Ok(LoadedAsset::Terrain(terrain)) => loaded_lunar_surface = Some(terrain),
+                    Ok(LoadedAsset::Helicopter(helicopter)) => loaded_helicopter = Some(*helicopter),
+                    Err(message) => {
+                        eprintln!("{}", message);
+                        load_failures += 1;
+                    }
+                }
+            }
+
+            // Resizes are left pending for the main loop, we only need the current size here
+            let (screen_w, screen_h) = match window_size.lock() {
+                Ok(size) => (size.0, size.1),
+                Err(_) => (INITIAL_SCREEN_W, INITIAL_SCREEN_H),
+            };
+            unsafe {
+                draw_loading_screen(asset_loader.progress(), screen_w, screen_h);
+            }
+            context.swap_buffers().unwrap();
+        }
+        if load_failures > 0 {
+            panic!("{} of the models failed to load, see above", load_failures);
+        }
+
+        let lunar_surface: mesh::Mesh = loaded_lunar_surface.expect("Terrain model was never loaded");
+        // The terrain up close, and simplified for when the camera pulls away
+        let lunar_surface_meshes = unsafe {
+            [
+                GpuMesh::upload(&lunar_surface),
+                GpuMesh::upload(&simplify::vertex_clustering(&lunar_surface, 128)),
+                GpuMesh::upload(&simplify::vertex_clustering(&lunar_surface, 48)),
+            ]
         };
 
-        // Projective/perspective Matrix
-        let projective_matrix: glm::Mat4 = glm::perspective(
-            (INITIAL_SCREEN_W as f32) / (INITIAL_SCREEN_H as f32),
-            (60 as f32).to_radians(),
-            1.0 as f32,
-            1000.0 as f32,
+        /*********************************************************************/
+        /* Task 2: Helicopter Parenting */
+        /*********************************************************************/
+        let helicopter: mesh::Helicopter = loaded_helicopter.expect("Helicopter model was never loaded");
+        let helicopter_meshes = unsafe { HelicopterMeshes::upload(&helicopter) };
+        let mut scene = scene_graph::Scene::new();
+        let lunar_surface_node = scene.add_child(
+            scene.root(),
+            SceneNode::from_lod(lod::Lod::new(
+                lunar_surface_meshes
+                    .iter()
+                    .cloned()
+                    .zip([150.0, 400.0, 0.0])
+                    .map(|(mesh, threshold)| lod::LodLevel { mesh, threshold })
+                    .collect(),
+                lod::LodMetric::Distance,
+                0.15,
+            ))
+                .with_name("lunar_surface")
+                .with_layers(TERRAIN_LAYER),
         );
 
-        // Translate origin of the models to the negative z range
-        let view_matrix: glm::Mat4 = glm::mat4(
-            1.0, 0.0, 0.0, 0.0, //
-            0.0, 1.0, 0.0, 0.0, //
-            0.0, 0.0, 1.0, -75.0, //
-            0.0, 0.0, 0.0, 1.0, //
+        let helicopter_1 = Helicopter::new(
+            &mut scene,
+            lunar_surface_node,
+            "helicopter_1",
+            &helicopter_meshes,
+            &glm::Vec3::new(0.0, 0.0, 50.0),
+            &glm::vec3(0.0, 0.7, 0.4),
+            0.0,
         );
 
+        // The lead helicopter stands out in gold, while still sharing its meshes with the others
+        scene[helicopter_1.helicopter_node].material = Some(Material::default().with_tint(glm::vec4(1.0, 0.8, 0.3, 1.0)));
+
         /*********************************************************************/
-        /* Task 2: Helicopter Parenting */
+        /* Task 6: Animate At least 5 helicopters */
         /*********************************************************************/
-        let mut helicopter = mesh::Helicopter::load("./resources/helicopter.obj");
-        let mut root_node = scene_graph::SceneNode::new();
-        let mut lunar_surface_node =
-            SceneNode::from_vao(lunar_surface_vao, lunar_surface.index_count);
-
-        let mut helicopter_1 = unsafe {
-            Helicopter::new(
-                &helicopter,
-                &glm::Vec3::new(0.0, 0.0, 50.0),
-                &glm::vec3(0.0, 0.7, 0.4),
-            )
-        };
+        let helicopter_2 = Helicopter::new(
+            &mut scene,
+            lunar_surface_node,
+            "helicopter_2",
+            &helicopter_meshes,
+            &glm::Vec3::new(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 0.7, 0.4),
+            0.0,
+        );
+
+        let helicopter_3 = Helicopter::new(
+            &mut scene,
+            lunar_surface_node,
+            "helicopter_3",
+            &helicopter_meshes,
+            &glm::Vec3::new(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 0.7, 0.4),
+            0.75,
+        );
+
+        let helicopter_4 = Helicopter::new(
+            &mut scene,
+            lunar_surface_node,
+            "helicopter_4",
+            &helicopter_meshes,
+            &glm::Vec3::new(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 0.7, 0.4),
+            1.50,
+        );
+
+        let helicopter_5 = Helicopter::new(
+            &mut scene,
+            lunar_surface_node,
+            "helicopter_5",
+            &helicopter_meshes,
+            &glm::Vec3::new(0.0, 10.0, 0.0),
+            &glm::vec3(0.0, 0.7, 0.4),
+            1.50,
+        );
+
+        let helicopter_6 = Helicopter::new(
+            &mut scene,
+            lunar_surface_node,
+            "helicopter_6",
+            &helicopter_meshes,
+            &glm::Vec3::new(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 0.7, 0.4),
+            2.25,
+        );
+
+        let helicopter_7 = Helicopter::new(
+            &mut scene,
+            lunar_surface_node,
+            "helicopter_7",
+            &helicopter_meshes,
+            &glm::Vec3::new(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 0.7, 0.4),
+            3.0,
+        );
+
+        let helicopter_8 = Helicopter::new(
+            &mut scene,
+            lunar_surface_node,
+            "helicopter_8",
+            &helicopter_meshes,
+            &glm::Vec3::new(0.0, 10.0, 0.0),
+            &glm::vec3(0.0, 0.7, 0.4),
+            3.0,
+        );
+
+        // The two flying high hover up and down, out of step with each other
+        scene[helicopter_5.helicopter_node].behaviours.push(Box::new(behaviour::Bobbing::new(1.5, 0.25, 0.0)));
+        scene[helicopter_8.helicopter_node].behaviours.push(Box::new(behaviour::Bobbing::new(1.5, 0.25, std::f32::consts::PI)));
 
         /*********************************************************************/
-        /* Task 6: Animate At least 5 helicopters */
+        /* Cameras and lights */
         /*********************************************************************/
-        let mut helicopter_2 = unsafe {
-            Helicopter::new(
-                &helicopter,
-                &glm::Vec3::new(0.0, 0.0, 0.0),
-                &glm::vec3(0.0, 0.7, 0.4),
-            )
-        };
+        // The free camera orbits the origin: the rig turns it around (pitch, then yaw) and the
+        // camera moves within the rig, starting 75 units out along Z
+        let camera_rig = scene.add_child(
+            scene.root(),
+            SceneNode::new()
+                .with_name("camera_rig")
+                .with_rotation_order(rotation::EulerOrder::YXZ),
+        );
+        let free_camera = scene.add_child(
+            camera_rig,
+            SceneNode::new()
+                .with_name("free_camera")
+                .with_position(glm::vec3(0.0, 0.0, 75.0))
+                .with_camera(camera::Camera::new(60.0_f32.to_radians(), 1.0, 1000.0)),
+        );
 
-        let mut helicopter_3 = unsafe {
-            Helicopter::new(
-                &helicopter,
-                &glm::Vec3::new(0.0, 0.0, 0.0),
-                &glm::vec3(0.0, 0.7, 0.4),
-            )
-        };
+        // The lead helicopter has a view from its cockpit, and a searchlight under its nose
+        scene.add_child(
+            helicopter_1.helicopter_node,
+            SceneNode::new()
+                .with_name("cockpit_camera")
+                .with_position(glm::vec3(0.0, 3.0, -1.0))
+                .with_camera(camera::Camera::new(75.0_f32.to_radians(), 0.1, 1000.0)),
+        );
+        scene.add_child(
+            helicopter_1.body_node,
+            SceneNode::new()
+                .with_name("searchlight")
+                .with_position(glm::vec3(0.0, 0.0, -1.0))
+                .with_rotation(glm::vec3(-1.2, 0.0, 0.0)) // Down and a bit ahead
+                .with_light(light::Light::new(
+                    light::LightKind::Spot {
+                        range: 60.0,
+                        inner_angle: 15.0_f32.to_radians(),
+                        outer_angle: 25.0_f32.to_radians(),
+                    },
+                    glm::vec3(1.0, 0.95, 0.8),
+                    2.0,
+                )),
+        );
 
-        let mut helicopter_4 = unsafe {
-            Helicopter::new(
-                &helicopter,
-                &glm::Vec3::new(0.0, 0.0, 0.0),
-                &glm::vec3(0.0, 0.7, 0.4),
-            )
-        };
+        // Moonlight over the whole scene
+        let mut moonlight = SceneNode::new()
+            .with_name("moonlight")
+            .with_light(light::Light::new(light::LightKind::Directional, glm::vec3(1.0, 1.0, 1.0), 1.0));
+        moonlight.look_at(&glm::vec3(0.8, -0.5, 0.6), &glm::vec3(0.0, 1.0, 0.0));
+        scene.add_child(scene.root(), moonlight);
 
-        let mut helicopter_5 = unsafe {
-            Helicopter::new(
-                &helicopter,
-                &glm::Vec3::new(0.0, 10.0, 0.0),
-                &glm::vec3(0.0, 0.7, 0.4),
-            )
+        /*********************************************************************/
+        /* Inverse kinematics */
+        /*********************************************************************/
+        // The lead helicopter also has a winch arm of three segments under it, which reaches for
+        // a spot on the ground as it flies past. Every segment is a capsule hanging down from
+        // its joint, and the joints bend up to about 60 degrees either way without twisting.
+        let arm_segment = unsafe {
+            GpuMesh::upload(&marching_cubes::VoxelGrid::from_fn(
+                |point| {
+                    let on_axis = glm::vec3(0.0, point.y.clamp(-1.5, 0.0), 0.0);
+                    glm::distance(point, &on_axis) - 0.15
+                },
+                glm::vec3(-0.25, -1.75, -0.25),
+                glm::vec3(0.25, 0.25, 0.25),
+                [8, 32, 8],
+            ).marching_cubes(0.0, [0.6, 0.6, 0.65, 1.0]))
         };
+        let mut arm_parent = helicopter_1.body_node;
+        let mut arm_joints = vec![];
+        for (i, offset) in [-0.5, -1.5, -1.5].iter().enumerate() {
+            arm_parent = scene.add_child(
+                arm_parent,
+                SceneNode::from_mesh(Rc::clone(&arm_segment))
+                    .with_name(&format!("winch_arm_{}", i + 1))
+                    .with_layers(HELICOPTER_LAYER)
+                    .with_position(glm::vec3(0.0, *offset, 0.0)),
+            );
+            arm_joints.push(arm_parent);
+        }
+        let winch_hook = scene.add_child(
+            arm_parent,
+            SceneNode::new().with_name("winch_hook").with_position(glm::vec3(0.0, -1.5, 0.0)),
+        );
+        let winch_target = scene.add_child(
+            lunar_surface_node,
+            SceneNode::new().with_name("winch_target").with_position(glm::vec3(0.0, -3.0, 45.0)),
+        );
+        let mut arm_chain = ik::IkChain::new(&scene, arm_joints[0], winch_hook);
+        for &joint in &arm_joints {
+            let limit = ik::JointLimit { min: glm::vec3(-1.0, 0.0, -1.0), max: glm::vec3(1.0, 0.0, 1.0) };
+            arm_chain = arm_chain.with_limit(joint, limit);
+        }
+        scene[arm_joints[0]].behaviours.push(Box::new(ik::Reach {
+            chain: arm_chain,
+            target: winch_target,
+            solver: ik::IkSolver::Fabrik,
+        }));
 
-        let mut helicopter_6 = unsafe {
-            Helicopter::new(
-                &helicopter,
-                &glm::Vec3::new(0.0, 0.0, 0.0),
-                &glm::vec3(0.0, 0.7, 0.4),
-            )
+        /*********************************************************************/
+        /* Saving and loading the scene */
+        /*********************************************************************/
+        // `--load-scene <file>` adds the nodes of a saved scene to the one built above, leaving
+        // out the cameras and lights it already has, and `--save-scene <file>` writes the result
+        // to a file. For debugging, `--print-scene` prints the hierarchy and `--export-dot <file>`
+        // writes it as a Graphviz graph, and `--render-stats` prints how many nodes were drawn
+        // and culled every second.
+        let arguments: Vec<String> = std::env::args().collect();
+        let argument = |flag: &str| {
+            arguments.iter().position(|a| a == flag).and_then(|i| arguments.get(i + 1)).cloned()
         };
 
-        let mut helicopter_7 = unsafe {
-            Helicopter::new(
-                &helicopter,
-                &glm::Vec3::new(0.0, 0.0, 0.0),
-                &glm::vec3(0.0, 0.7, 0.4),
-            )
-        };
+        if let Some(path) = argument("--load-scene") {
+            let mut scene_file = scene_file::SceneFile::load(&path).unwrap_or_else(|e| panic!("{}", e));
+            scene_file.drop_duplicate_cameras_and_lights(&scene, scene.root());
+            // Every distinct mesh is loaded and uploaded only once, including the ones already
+            // in the scene
+            let root = scene.root();
+            let mut uploaded: Vec<Rc<GpuMesh>> = scene.depth_first(root)
+                .filter_map(|visit| visit.node.primary_mesh().cloned())
+                .collect();
+            scene_file.instantiate(&mut scene, root, &mut |mesh_ref| {
+                if let Some(mesh) = uploaded.iter().find(|mesh| mesh.source.as_ref() == Some(mesh_ref)) {
+                    return Ok(Rc::clone(mesh));
+                }
+                let mesh = unsafe { GpuMesh::upload(&mesh_ref.load()?) };
+                uploaded.push(Rc::clone(&mesh));
+                Ok(mesh)
+            }).unwrap_or_else(|e| panic!("{}", e));
+        }
 
-        let mut helicopter_8 = unsafe {
-            Helicopter::new(
-                &helicopter,
-                &glm::Vec3::new(0.0, 10.0, 0.0),
-                &glm::vec3(0.0, 0.7, 0.4),
-            )
-        };
+        if let Some(path) = argument("--save-scene") {
+            scene_file::SceneFile::from_scene(&scene).save(&path).unwrap_or_else(|e| panic!("{}", e));
+            println!("Saved the scene to {}", path);
+        }
 
-        lunar_surface_node.add_child(&helicopter_1.helicopter_node);
-        lunar_surface_node.add_child(&helicopter_2.helicopter_node);
-        lunar_surface_node.add_child(&helicopter_3.helicopter_node);
-        lunar_surface_node.add_child(&helicopter_4.helicopter_node);
-        lunar_surface_node.add_child(&helicopter_5.helicopter_node);
-        lunar_surface_node.add_child(&helicopter_6.helicopter_node);
-        lunar_surface_node.add_child(&helicopter_7.helicopter_node);
-        lunar_surface_node.add_child(&helicopter_8.helicopter_node);
+        if arguments.iter().any(|a| a == "--print-scene") {
+            scene.print_tree(scene.root());
+        }
 
-        root_node.add_child(&lunar_surface_node);
+        let print_render_stats = arguments.iter().any(|a| a == "--render-stats");
+
+        if let Some(path) = argument("--export-dot") {
+            scene.write_dot(scene.root(), &path).expect("Failed to write the Graphviz file");
+            println!("Exported the scene graph to {}", path);
+        }
 
         /*********************************************************************/
         /* Main loop functions */
         /*********************************************************************/
-        // Camera position and orientation
-        let mut x: f32 = 0.0;
-        let mut y: f32 = 0.0;
-        let mut z: f32 = 0.0;
-        let mut pitch: f32 = 0.0; // rotate about x
-        let mut yaw: f32 = 0.0; // rotate about y
+        // The camera the scene is drawn from, C switches to the next one
+        let mut active_camera = free_camera;
 
         // == // Set up your shaders here
         let shaders = unsafe {
@@ -533,12 +878,39 @@ fn main() {
                 .attach_file("./shaders/simple.vert")
                 .attach_file("./shaders/simple.frag")
                 .link()
-                .activate();
         };
+        unsafe {
+            shaders.activate();
+        }
+
+        // Where every node with a mesh is, kept up to date as the helicopters fly
+        let mut scene_bvh = scene_bvh::SceneBvh::new();
+
+        // Every helicopter collides with the others as a whole, by the sphere around it
+        let mut collisions = collision::CollisionDetector::new();
+        for helicopter in [
+            &helicopter_1, &helicopter_2, &helicopter_3, &helicopter_4,
+            &helicopter_5, &helicopter_6, &helicopter_7, &helicopter_8,
+        ] {
+            collisions.add(helicopter.helicopter_node, collision::Shape::Sphere);
+        }
+        // How many contacts every collider is in
+        let mut contact_counts: HashMap<NodeId, usize> = HashMap::new();
+        // The emissive colors of the glowing colliders from before they began touching, or None
+        // for those that had no material of their own
+        let mut emissive_before_glow: HashMap<NodeId, Option<Option<glm::Vec3>>> = HashMap::new();
 
         // The main rendering loop
         let first_frame_time = std::time::Instant::now();
         let mut previous_frame_time = first_frame_time;
+        let mut previous_stats_time = first_frame_time;
+
+        // Editing: clicking selects a node, V hides or shows it, Delete removes it, Z undoes and
+        // Y redoes
+        let mut history = history::History::new();
+        let mut selected: Option<NodeId> = None;
+        let mut previous_keys: Vec<VirtualKeyCode> = vec![];
+        let mut newly_pressed: Vec<VirtualKeyCode> = vec![];
 
         loop {
             // Compute time passed since the previous frame and since the start of the program
@@ -564,6 +936,9 @@ fn main() {
             let translate_camera_speed = 25.0;
             let rotate_camera_speed = 1.0;
 
+            // Which render layers to draw, everything unless a key below says otherwise
+            let mut layer_mask = scene_graph::ALL_LAYERS;
+
             // Handle keyboard input
             if let Ok(keys) = pressed_keys.lock() {
                 for key in keys.iter() {
@@ -571,39 +946,52 @@ fn main() {
                         // The `VirtualKeyCode` enum is defined here:
                         //    https://docs.rs/winit/0.25.0/winit/event/enum.VirtualKeyCode.html
                         VirtualKeyCode::A => {
-                            x += delta_time * translate_camera_speed;
+                            scene[free_camera].position.x -= delta_time * translate_camera_speed;
                         }
                         VirtualKeyCode::D => {
-                            x -= delta_time * translate_camera_speed;
+                            scene[free_camera].position.x += delta_time * translate_camera_speed;
                         }
                         VirtualKeyCode::S => {
-                            y += delta_time * translate_camera_speed;
+                            scene[free_camera].position.y -= delta_time * translate_camera_speed;
                         }
                         VirtualKeyCode::W => {
-                            y -= delta_time * translate_camera_speed;
+                            scene[free_camera].position.y += delta_time * translate_camera_speed;
                         }
                         VirtualKeyCode::LControl => {
-                            z += delta_time * translate_camera_speed;
+                            scene[free_camera].position.z -= delta_time * translate_camera_speed;
                         }
                         VirtualKeyCode::LShift => {
-                            z -= delta_time * translate_camera_speed;
+                            scene[free_camera].position.z += delta_time * translate_camera_speed;
                         }
+                        // Limit camera motion to looking between straight down and straight up
                         VirtualKeyCode::Up => {
-                            pitch += delta_time * rotate_camera_speed;
+                            let rig = &mut scene[camera_rig];
+                            rig.rotation.x = (rig.rotation.x - delta_time * rotate_camera_speed).max(-std::f32::consts::PI);
                         }
                         VirtualKeyCode::Down => {
-                            pitch -= delta_time * rotate_camera_speed;
+                            let rig = &mut scene[camera_rig];
+                            rig.rotation.x = (rig.rotation.x + delta_time * rotate_camera_speed).min(0.0);
                         }
                         VirtualKeyCode::Left => {
-                            yaw += delta_time * rotate_camera_speed;
+                            scene[camera_rig].rotation.y -= delta_time * rotate_camera_speed;
                         }
                         VirtualKeyCode::Right => {
-                            yaw -= delta_time * rotate_camera_speed;
+                            scene[camera_rig].rotation.y += delta_time * rotate_camera_speed;
+                        }
+                        // Hold to show only the terrain or only the helicopters
+                        VirtualKeyCode::T => {
+                            layer_mask = TERRAIN_LAYER;
+                        }
+                        VirtualKeyCode::H => {
+                            layer_mask = HELICOPTER_LAYER;
                         }
 
                         _ => {}
                     }
                 }
+                // Edits happen once per key press, not on every frame the key is held
+                newly_pressed = keys.iter().copied().filter(|key| !previous_keys.contains(key)).collect();
+                previous_keys = keys.clone();
             }
             // Handle mouse movement. delta contains the x and y movement of the mouse since last frame in pixels
             if let Ok(mut delta) = mouse_delta.lock() {
@@ -613,46 +1001,133 @@ fn main() {
                 *delta = (0.0, 0.0); // reset when done
             }
 
+            /*********************************************************************/
+            /* Task 4 and 6 - Spin the rotors and animate the helicopters */
+            /*********************************************************************/
+            // The helicopters fly and their rotors spin by the behaviours attached to their nodes
+            scene.update_behaviours(&behaviour::FrameTime { elapsed, delta: delta_time });
+            scene_bvh.update(&scene);
+
             /*********************************************************************/
             /* Camera transforms */
             /*********************************************************************/
-            // Limit camera motion
-            let min_pitch: f32 = (0.0 as f32).to_radians();
-            let max_pitch: f32 = (180.0 as f32).to_radians();
-            if pitch > max_pitch {
-                pitch = max_pitch;
-            }
-            if pitch < min_pitch {
-                pitch = min_pitch;
+            // After the animation, so a camera on a helicopter does not lag a frame behind it. A
+            // removed or detached camera hands over to the free one.
+            if !scene.contains(active_camera) || !scene.is_ancestor(scene.root(), active_camera) {
+                active_camera = free_camera;
             }
+            let transforms_matrix = scene.view_projection_matrix(active_camera, window_aspect_ratio);
+            let lights = scene.lights(scene.root());
 
-            let mut transforms_matrix: glm::Mat4 = glm::identity();
-            let mut transformation_so_far: glm::Mat4 = glm::identity();
-            transforms_matrix = projective_matrix * view_matrix;
-
-            transforms_matrix = glm::translate(&transforms_matrix, &glm::vec3(x, y, z));
-            transforms_matrix = glm::rotate_x(&transforms_matrix, pitch);
-            transforms_matrix = glm::rotate_y(&transforms_matrix, yaw);
+            /*********************************************************************/
+            /* Collisions */
+            /*********************************************************************/
+            // Helicopters glow red for as long as they touch another one
+            for event in collisions.detect(&scene) {
+                let (a, b, began) = match event {
+                    collision::ContactEvent::Began(contact) => {
+                        println!("{} collided with {}", scene.path(contact.a), scene.path(contact.b));
+                        (contact.a, contact.b, true)
+                    }
+                    collision::ContactEvent::Ended(a, b) => (a, b, false),
+                    collision::ContactEvent::Persisted(_) => continue,
+                };
+                for id in [a, b] {
+                    let count = contact_counts.entry(id).or_insert(0);
+                    if began { *count += 1 } else { *count -= 1 }
+                    let node = match scene.get_mut(id) {
+                        Some(node) => node,
+                        None => {
+                            emissive_before_glow.remove(&id);
+                            continue;
+                        }
+                    };
+                    if began && *count == 1 {
+                        emissive_before_glow.insert(id, node.material.as_ref().map(|material| material.emissive));
+                        node.material.get_or_insert_with(Material::default).emissive = Some(glm::vec3(0.6, 0.05, 0.0));
+                    } else if *count == 0 {
+                        match emissive_before_glow.remove(&id) {
+                            Some(Some(emissive)) => {
+                                node.material.get_or_insert_with(Material::default).emissive = emissive;
+                            }
+                            // Only drop the material the glow added if nothing else was set on it since
+                            Some(None) => {
+                                if let Some(material) = &mut node.material {
+                                    material.emissive = None;
+                                }
+                                if node.material == Some(Material::default()) {
+                                    node.material = None;
+                                }
+                            }
+                            None => {}
+                        }
+                    }
+                }
+            }
 
             /*********************************************************************/
-            /* Task 4a - Spin Helicopter Rotors */
+            /* Mouse picking */
             /*********************************************************************/
-            // helicopter_1.spin_rotors(
-            //     glm::Vec3::new(0.0, 5.0 * elapsed, 0.0),
-            //     glm::Vec3::new(5.0 * elapsed, 0.0, 0.0),
-            // );
+            // Report the node under the cursor for every click, as drawn this frame
+            if let Ok(mut clicks) = mouse_clicks.lock() {
+                let (screen_w, screen_h) = match window_size.lock() {
+                    Ok(size) => (size.0, size.1),
+                    Err(_) => (INITIAL_SCREEN_W, INITIAL_SCREEN_H),
+                };
+                for &cursor in clicks.iter() {
+                    let ray = picking::cursor_ray(cursor, screen_w, screen_h, &transforms_matrix);
+                    let hit = picking::pick(&scene, &scene_bvh, &ray, f32::INFINITY, layer_mask);
+                    match &hit {
+                        Some(hit) => println!(
+                            "Picked {} at ({:.2}, {:.2}, {:.2}), {:.2} away",
+                            scene.path(hit.node),
+                            hit.position.x,
+                            hit.position.y,
+                            hit.position.z,
+                            hit.distance,
+                        ),
+                        None => println!("Picked nothing"),
+                    }
+                    selected = hit.map(|hit| hit.node);
+                }
+                clicks.clear();
+            }
 
             /*********************************************************************/
-            /* Task 4b and 6 - Animate  Helicopter */
+            /* Editing */
             /*********************************************************************/
-            helicopter_1.animate_helicopter(elapsed, 0.0);
-            helicopter_2.animate_helicopter(elapsed, 0.0);
-            helicopter_3.animate_helicopter(elapsed, 0.75);
-            helicopter_4.animate_helicopter(elapsed, 1.50);
-            helicopter_5.animate_helicopter(elapsed, 1.50);
-            helicopter_6.animate_helicopter(elapsed, 2.25);
-            helicopter_7.animate_helicopter(elapsed, 3.0);
-            helicopter_8.animate_helicopter(elapsed, 3.0);
+            for key in &newly_pressed {
+                match key {
+                    VirtualKeyCode::V => {
+                        if let Some(id) = selected.filter(|&id| scene.contains(id)) {
+                            history.edit(&mut scene, id, "toggle visibility", |node| node.visible = !node.visible);
+                            // Every press is its own step, not merged with the next one
+                            history.seal();
+                        }
+                    }
+                    VirtualKeyCode::Delete => {
+                        if let Some(id) = selected.take().filter(|&id| scene.contains(id)) {
+                            println!("Removed {}", scene.path(id));
+                            history.remove(&mut scene, id);
+                        }
+                    }
+                    VirtualKeyCode::Z => match history.undo(&mut scene) {
+                        Some(label) => println!("Undid {}", label),
+                        None => println!("Nothing to undo"),
+                    },
+                    VirtualKeyCode::Y => match history.redo(&mut scene) {
+                        Some(label) => println!("Redid {}", label),
+                        None => println!("Nothing to redo"),
+                    },
+                    VirtualKeyCode::C => {
+                        let cameras = scene.cameras(scene.root());
+                        let next = cameras.iter().position(|&id| id == active_camera).map_or(0, |i| i + 1);
+                        active_camera = cameras[next % cameras.len()];
+                        println!("Viewing from {}", scene.path(active_camera));
+                    }
+                    _ => {}
+                }
+            }
 
             unsafe {
                 // Clear the color and depth buffers
@@ -660,7 +1135,14 @@ fn main() {
                 gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
                 // == // Issue the necessary gl:: commands to draw your scene here
-                draw_scene(&root_node, &transforms_matrix, &transformation_so_far);
+                let stats = draw_scene(&scene, scene.root(), &transforms_matrix, &lights, layer_mask, &shaders);
+                if print_render_stats && now.duration_since(previous_stats_time).as_secs_f32() >= 1.0 {
+                    println!(
+                        "{} nodes drawn, {} culled, {} draw calls",
+                        stats.drawn, stats.culled, stats.draw_calls
+                    );
+                    previous_stats_time = now;
+                }
             }
 
             // Display the new color buffer on the display
@@ -684,6 +1166,9 @@ fn main() {
         }
     });
 
+    // The cursor position is only sent with the events that move it, so remember it for clicks
+    let mut cursor_position = (0f32, 0f32);
+
     // Start the event loop -- This is where window events are initially handled
     el.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
@@ -755,6 +1240,25 @@ fn main() {
                     _ => {}
                 }
             }
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
+            } => {
+                cursor_position = (position.x as f32, position.y as f32);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state: Pressed,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                ..
+            } => {
+                if let Ok(mut clicks) = arc_mouse_clicks.lock() {
+                    clicks.push(cursor_position);
+                }
+            }
             Event::DeviceEvent {
                 event: DeviceEvent::MouseMotion { delta },
                 ..
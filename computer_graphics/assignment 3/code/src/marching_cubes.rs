@@ -0,0 +1,240 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::mesh::Mesh;
+
+// Isosurface extraction with marching cubes. A scalar field is sampled on a regular grid (a
+// `VoxelGrid`), and the surface where it crosses `iso` is turned into a welded `Mesh`: vertices on
+// grid edges are shared between neighbouring cubes, and the normals come from the gradient of the
+// field rather than from the triangles. Values below `iso` are inside, so signed distance
+// functions (negative inside) work as they are, and the normals point towards the outside.
+//
+// Instead of the classic hand written 256 entry triangle table, the table is generated once from
+// the topology of the cube: on each face the crossings are connected into segments, and the
+// segments are chained into polygons. Faces with two diagonal inside corners always keep those
+// corners apart, and since that choice only depends on the face itself, neighbouring cubes agree
+// on it and the surface has no cracks.
+
+pub struct VoxelGrid {
+    pub dims    : [usize; 3],  // Number of samples along each axis
+    pub origin  : glm::Vec3,   // Position of the first sample
+    pub spacing : glm::Vec3,   // Distance between neighbouring samples
+    pub values  : Vec<f32>,    // X varies fastest, then Y, then Z
+}
+
+impl VoxelGrid {
+    pub fn new(dims: [usize; 3], origin: glm::Vec3, spacing: glm::Vec3) -> VoxelGrid {
+        VoxelGrid {
+            dims,
+            origin,
+            spacing,
+            values: vec![0.0; dims[0] * dims[1] * dims[2]],
+        }
+    }
+
+    // Sample `field` on a grid spanning `min` to `max`, e.g. a signed distance function or noise
+    pub fn from_fn<F: Fn(&glm::Vec3) -> f32>(field: F, min: glm::Vec3, max: glm::Vec3, dims: [usize; 3]) -> VoxelGrid {
+        let steps = glm::vec3(
+            (dims[0].max(2) - 1) as f32,
+            (dims[1].max(2) - 1) as f32,
+            (dims[2].max(2) - 1) as f32,
+        );
+        let mut grid = VoxelGrid::new(dims, min, (max - min).component_div(&steps));
+        for z in 0..dims[2] {
+            for y in 0..dims[1] {
+                for x in 0..dims[0] {
+                    let index = grid.index(x, y, z);
+                    grid.values[index] = field(&grid.position(x, y, z));
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + self.dims[0] * (y + self.dims[1] * z)
+    }
+
+    pub fn value(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.values[self.index(x, y, z)]
+    }
+
+    pub fn position(&self, x: usize, y: usize, z: usize) -> glm::Vec3 {
+        self.origin + glm::vec3(x as f32, y as f32, z as f32).component_mul(&self.spacing)
+    }
+
+    // Gradient at a grid sample by central differences, one sided on the border
+    pub fn gradient(&self, x: usize, y: usize, z: usize) -> glm::Vec3 {
+        let sample = [x, y, z];
+        let mut gradient: glm::Vec3 = glm::zero();
+        for axis in 0..3 {
+            let mut lower = sample;
+            let mut upper = sample;
+            lower[axis] = sample[axis].saturating_sub(1);
+            upper[axis] = (sample[axis] + 1).min(self.dims[axis] - 1);
+            if upper[axis] == lower[axis] {
+                continue;
+            }
+            let difference = self.value(upper[0], upper[1], upper[2]) - self.value(lower[0], lower[1], lower[2]);
+            gradient[axis] = difference / ((upper[axis] - lower[axis]) as f32 * self.spacing[axis]);
+        }
+        gradient
+    }
+
+    pub fn marching_cubes(&self, iso: f32, color: [f32; 4]) -> Mesh {
+        let table = case_table();
+        let mut vertices: Vec<f32> = vec![];
+        let mut normals: Vec<f32> = vec![];
+        let mut indices: Vec<u32> = vec![];
+        // Grid edges are identified by their lower sample and their axis
+        let mut vertex_on_edge: HashMap<(usize, usize), u32> = HashMap::new();
+
+        if self.dims.iter().any(|&n| n < 2) {
+            return Mesh::from_arrays(vertices, normals, indices, color);
+        }
+
+        for z in 0..self.dims[2] - 1 {
+            for y in 0..self.dims[1] - 1 {
+                for x in 0..self.dims[0] - 1 {
+                    let mut case = 0;
+                    for corner in 0..8 {
+                        let [cx, cy, cz] = corner_offset(corner);
+                        if self.value(x + cx, y + cy, z + cz) < iso {
+                            case |= 1 << corner;
+                        }
+                    }
+
+                    for triangle in &table[case] {
+                        let mut corners = [0u32; 3];
+                        for (i, &edge) in triangle.iter().enumerate() {
+                            let (a, b) = CUBE_EDGES[edge];
+                            let [ax, ay, az] = corner_offset(a);
+                            let [bx, by, bz] = corner_offset(b);
+                            let lower = self.index(x + ax, y + ay, z + az);
+                            let axis = edge / 4; // CUBE_EDGES is grouped by axis
+                            corners[i] = *vertex_on_edge.entry((lower, axis)).or_insert_with(|| {
+                                let va = self.value(x + ax, y + ay, z + az);
+                                let vb = self.value(x + bx, y + by, z + bz);
+                                let t = if va == vb { 0.5 } else { ((iso - va) / (vb - va)).clamp(0.0, 1.0) };
+                                let position = glm::lerp(&self.position(x + ax, y + ay, z + az), &self.position(x + bx, y + by, z + bz), t);
+                                let gradient = glm::lerp(&self.gradient(x + ax, y + ay, z + az), &self.gradient(x + bx, y + by, z + bz), t);
+                                let normal = if glm::length(&gradient) > 0.0 { glm::normalize(&gradient) } else { gradient };
+                                vertices.extend_from_slice(&[position.x, position.y, position.z]);
+                                normals.extend_from_slice(&[normal.x, normal.y, normal.z]);
+                                (vertices.len() / 3 - 1) as u32
+                            });
+                        }
+                        // The surface can touch a sample exactly, which collapses the triangle
+                        if corners[0] != corners[1] && corners[1] != corners[2] && corners[2] != corners[0] {
+                            indices.extend_from_slice(&corners);
+                        }
+                    }
+                }
+            }
+        }
+
+        Mesh::from_arrays(vertices, normals, indices, color)
+    }
+}
+
+// Corner `c` of a cube sits at (c & 1, (c >> 1) & 1, (c >> 2) & 1)
+fn corner_offset(corner: usize) -> [usize; 3] {
+    [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1]
+}
+
+// Pairs of corners, four edges along X, then Y, then Z, the first corner being the lower one
+const CUBE_EDGES: [(usize, usize); 12] = [
+    (0, 1), (2, 3), (4, 5), (6, 7),
+    (0, 2), (1, 3), (4, 6), (5, 7),
+    (0, 4), (1, 5), (2, 6), (3, 7),
+];
+
+// For each of the 256 inside/outside combinations of the corners, the triangles as triples of
+// cube edges, wound counter clockwise when seen from the outside of the surface
+fn case_table() -> &'static Vec<Vec<[usize; 3]>> {
+    static TABLE: OnceLock<Vec<Vec<[usize; 3]>>> = OnceLock::new();
+    TABLE.get_or_init(|| (0..256).map(triangulate_case).collect())
+}
+
+fn triangulate_case(case: usize) -> Vec<[usize; 3]> {
+    let inside = |corner: usize| case & (1 << corner) != 0;
+    let edge_between = |a: usize, b: usize| {
+        CUBE_EDGES.iter().position(|&(c, d)| (c == a && d == b) || (c == b && d == a)).unwrap()
+    };
+
+    // Directed segments, `next[e]` is the edge the surface continues to after edge `e`
+    let mut next: [Option<usize>; 12] = [None; 12];
+    for axis in 0..3 {
+        for side in 0..2 {
+            // The face's corners, counter clockwise when looking at it from outside the cube
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let mut face: Vec<usize> = [(0, 0), (1, 0), (1, 1), (0, 1)].iter()
+                .map(|&(du, dv)| (side << axis) | (du << u) | (dv << v))
+                .collect();
+            if side == 0 {
+                face.reverse();
+            }
+
+            // Walking around the face, crossings alternate between leaving and entering the inside
+            let crossings: Vec<(usize, bool)> = (0..4)
+                .filter(|&i| inside(face[i]) != inside(face[(i + 1) % 4]))
+                .map(|i| (edge_between(face[i], face[(i + 1) % 4]), inside(face[(i + 1) % 4])))
+                .collect();
+            // Each crossing into the inside connects to the next one leaving it, which cuts off
+            // the inside corners in between, keeping diagonal inside corners apart
+            for (i, &(edge, entering)) in crossings.iter().enumerate() {
+                if entering {
+                    let (leaving_edge, _) = crossings[(i + 1) % crossings.len()];
+                    next[edge] = Some(leaving_edge);
+                }
+            }
+        }
+    }
+
+    // Chain the segments into closed polygons and fan them into triangles
+    let mut triangles = vec![];
+    let mut visited = [false; 12];
+    for start in 0..12 {
+        if visited[start] || next[start].is_none() {
+            continue;
+        }
+        let mut polygon = vec![];
+        let mut edge = start;
+        while !visited[edge] {
+            visited[edge] = true;
+            polygon.push(edge);
+            edge = next[edge].expect("Marching cubes segments do not form a closed polygon");
+        }
+
+        // A fan diagonal between two points on the same cube face would put a triangle flat in
+        // that face, where it overlaps the neighbouring cube's surface. Fan from a corner of the
+        // polygon whose diagonals all cut through the cube instead.
+        let n = polygon.len();
+        let apex = (0..n)
+            .min_by_key(|&apex| {
+                (2..n - 1)
+                    .filter(|&i| edge_faces(polygon[apex]) & edge_faces(polygon[(apex + i) % n]) != 0)
+                    .count()
+            })
+            .unwrap_or(0);
+        for i in 1..n - 1 {
+            triangles.push([polygon[apex], polygon[(apex + i) % n], polygon[(apex + i + 1) % n]]);
+        }
+    }
+    triangles
+}
+
+// The two cube faces an edge lies on, as a bit mask over (axis, side) with bit 2 * axis + side
+fn edge_faces(edge: usize) -> u32 {
+    let (a, b) = CUBE_EDGES[edge];
+    let along = edge / 4;
+    let mut faces = 0;
+    for axis in (0..3).filter(|&axis| axis != along) {
+        let side = (a >> axis) & 1;
+        debug_assert_eq!(side, (b >> axis) & 1);
+        faces |= 1 << (2 * axis + side);
+    }
+    faces
+}
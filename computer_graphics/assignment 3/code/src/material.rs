@@ -0,0 +1,104 @@
+extern crate nalgebra_glm as glm;
+
+use std::ffi::CString;
+
+// Overrides for how a node and everything below it is drawn, without touching the mesh. Every
+// field a node sets replaces what it would inherit from its parent, the rest is inherited:
+//  * `tint` multiplies the vertex colors, and `emissive` is added on top of the lighting. Both
+//    are per instance, so nodes sharing a mesh are still drawn together whatever their colors.
+//  * `shader` draws with another program (a `Shader::program_id`). It has to take the same
+//    attributes as simple.vert, and the view projection matrix at uniform location 0.
+//  * `uniforms` are set by name on the program before drawing. Uniforms keep their values in the
+//    program afterwards, so give every node using one a value for it.
+// Nodes with different shaders or uniforms cannot share a draw call.
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Material {
+    pub tint     : Option<glm::Vec4>,
+    pub emissive : Option<glm::Vec3>,
+    pub shader   : Option<u32>,
+    pub uniforms : Vec<(String, UniformValue)>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UniformValue {
+    Int(i32),
+    Float(f32),
+    Vec3(glm::Vec3),
+    Vec4(glm::Vec4),
+    Mat4(glm::Mat4),
+}
+
+impl Material {
+    pub fn with_tint(mut self, tint: glm::Vec4) -> Material {
+        self.tint = Some(tint);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_emissive(mut self, emissive: glm::Vec3) -> Material {
+        self.emissive = Some(emissive);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_shader(mut self, program_id: u32) -> Material {
+        self.shader = Some(program_id);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_uniform(mut self, name: &str, value: UniformValue) -> Material {
+        self.set_uniform(name, value);
+        self
+    }
+
+    pub fn set_uniform(&mut self, name: &str, value: UniformValue) {
+        match self.uniforms.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.uniforms.push((name.to_string(), value)),
+        }
+    }
+
+    // This material on top of the one inherited from the parent
+    pub fn inherit(&self, parent: &Material) -> Material {
+        let mut material = Material {
+            tint: self.tint.or(parent.tint),
+            emissive: self.emissive.or(parent.emissive),
+            shader: self.shader.or(parent.shader),
+            uniforms: parent.uniforms.clone(),
+        };
+        for (name, value) in &self.uniforms {
+            material.set_uniform(name, *value);
+        }
+        material
+    }
+
+    pub fn tint_or_white(&self) -> glm::Vec4 {
+        self.tint.unwrap_or(glm::vec4(1.0, 1.0, 1.0, 1.0))
+    }
+
+    pub fn emissive_or_black(&self) -> glm::Vec3 {
+        self.emissive.unwrap_or(glm::zero())
+    }
+
+    // Sets the named uniforms on `program_id`, which must be in use. Names the program does not
+    // have (or that the compiler optimized away) are skipped.
+    pub unsafe fn apply_uniforms(&self, program_id: u32) {
+        for (name, value) in &self.uniforms {
+            let name_cstr = CString::new(name.as_str()).expect("CString::new failed");
+            let location = gl::GetUniformLocation(program_id, name_cstr.as_ptr());
+            if location < 0 {
+                continue;
+            }
+            match value {
+                UniformValue::Int(v) => gl::Uniform1i(location, *v),
+                UniformValue::Float(v) => gl::Uniform1f(location, *v),
+                UniformValue::Vec3(v) => gl::Uniform3fv(location, 1, v.as_ptr()),
+                UniformValue::Vec4(v) => gl::Uniform4fv(location, 1, v.as_ptr()),
+                UniformValue::Mat4(v) => gl::UniformMatrix4fv(location, 1, gl::FALSE, v.as_ptr()),
+            }
+        }
+    }
+}
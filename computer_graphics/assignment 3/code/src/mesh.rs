@@ -1,10 +1,62 @@
+extern crate nalgebra_glm as glm;
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
 use tobj;
 
+use crate::ambient_occlusion::AmbientOcclusion;
+
+// The options every model in this project is parsed with
+pub fn load_options() -> tobj::LoadOptions {
+    tobj::LoadOptions{
+        triangulate: true,
+        single_index: true,
+        ..Default::default()
+    }
+}
+
 // internal helper
 fn generate_color_vec(color: [f32; 4], num: usize) -> Vec<f32> {
     color.iter().cloned().cycle().take(num*4).collect()
 }
 
+// Where a mesh was loaded from: a model in an OBJ file, the color it was given and the ambient
+// occlusion baked into it, if any. This is what saved scenes store instead of the geometry itself.
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct MeshRef {
+    pub file      : String,
+    pub model     : Option<String>,            // The name of the model in the file, or None for single model files
+    pub color     : [f32; 4],
+    #[serde(default)]
+    pub occlusion : Option<AmbientOcclusion>,  // Baked into the colors again when loading
+}
+
+impl MeshRef {
+    pub fn load(&self) -> Result<Mesh, String> {
+        let (models, _materials) = tobj::load_obj(&self.file, &load_options())
+            .map_err(|e| format!("Failed to load {}: {}", self.file, e))?;
+        let model = match &self.model {
+            Some(name) => models.into_iter()
+                .find(|model| &model.name == name)
+                .ok_or_else(|| format!("{} has no model called {}", self.file, name))?,
+            None if models.len() == 1 => models.into_iter().next().unwrap(),
+            None => return Err(format!("{} has {} models, say which one to use", self.file, models.len())),
+        };
+        let mut mesh = self.clone().into_mesh(model);
+        if let Some(occlusion) = &self.occlusion {
+            occlusion.bake_into_colors(&mut mesh);
+        }
+        Ok(mesh)
+    }
+
+    // The mesh of an already parsed model, remembering this as its source
+    fn into_mesh(self, model: tobj::Model) -> Mesh {
+        let mut mesh = Mesh::from(model.mesh, self.color);
+        mesh.source = Some(self);
+        mesh
+    }
+}
+
 // Mesh
 
 pub struct Mesh {
@@ -13,6 +65,7 @@ pub struct Mesh {
     pub colors      : Vec<f32>,
     pub indices     : Vec<u32>,
     pub index_count : i32,
+    pub source      : Option<MeshRef>,  // None for meshes generated in code
 }
 
 impl Mesh {
@@ -25,30 +78,91 @@ impl Mesh {
             indices: mesh.indices,
             colors: generate_color_vec(color, num_verts),
             index_count,
+            source: None,
         }
     }
+
+    // For meshes generated in code rather than loaded from a model, with a single color like `from`
+    pub fn from_arrays(vertices: Vec<f32>, normals: Vec<f32>, indices: Vec<u32>, color: [f32; 4]) -> Self {
+        let num_verts = vertices.len() / 3;
+        let index_count = indices.len() as i32;
+        Mesh {
+            vertices,
+            normals,
+            indices,
+            colors: generate_color_vec(color, num_verts),
+            index_count,
+            source: None,
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / 3
+    }
+
+    pub fn position(&self, index: u32) -> glm::Vec3 {
+        let i = index as usize * 3;
+        glm::vec3(self.vertices[i], self.vertices[i + 1], self.vertices[i + 2])
+    }
+
+    // The normals stored in the model, or the area weighted average of the surrounding faces if the
+    // model came without normals
+    pub fn vertex_normals(&self) -> Vec<glm::Vec3> {
+        if self.normals.len() == self.vertices.len() {
+            return self.normals.chunks_exact(3).map(|n| glm::vec3(n[0], n[1], n[2])).collect();
+        }
+        let mut normals: Vec<glm::Vec3> = vec![glm::zero(); self.vertex_count()];
+        for triangle in self.indices.chunks_exact(3) {
+            let a = self.position(triangle[0]);
+            let b = self.position(triangle[1]);
+            let c = self.position(triangle[2]);
+            let face_normal = glm::cross(&(b - a), &(c - a));
+            for &vertex in triangle {
+                normals[vertex as usize] += face_normal;
+            }
+        }
+        normals.iter()
+            .map(|n| if glm::length(n) > 0.0 { glm::normalize(n) } else { *n })
+            .collect()
+    }
+
+    // Maps every vertex to the first vertex sharing its exact position. The models are loaded with
+    // `single_index`, which duplicates vertices along normal/texture seams, so anything working on
+    // the shape of the surface should look at the welded vertices.
+    pub fn welded_vertices(&self) -> Vec<u32> {
+        let mut first_at_position: BTreeMap<[u32; 3], u32> = BTreeMap::new();
+        self.vertices.chunks_exact(3)
+            .enumerate()
+            .map(|(i, p)| {
+                // Adding zero turns -0.0 into 0.0, so the two compare equal
+                let key = [(p[0] + 0.0).to_bits(), (p[1] + 0.0).to_bits(), (p[2] + 0.0).to_bits()];
+                *first_at_position.entry(key).or_insert(i as u32)
+            })
+            .collect()
+    }
 }
 
 // Lunar terrain
 
 pub struct Terrain;
 impl Terrain {
-    pub fn load(path: &str) -> Mesh {
+    #[allow(dead_code)]
+    pub fn load(path: &str) -> Result<Mesh, String> {
         println!("Loading terrain model...");
         let before = std::time::Instant::now();
-        let (models, _materials)
-            = tobj::load_obj(path,
-                &tobj::LoadOptions{
-                    triangulate: true,
-                    single_index: true,
-                    ..Default::default()
-                }
-            ).expect("Failed to load terrain model");
+        let (models, _materials) = tobj::load_obj(path, &load_options())
+            .map_err(|e| format!("Failed to load terrain model {}: {}", path, e))?;
         let after = std::time::Instant::now();
         println!("Done in {:.3}ms.", after.duration_since(before).as_micros() as f32 / 1e3);
 
-        if models.len() > 1 || models.len() == 0 {
-            panic!("Please use a model with a single mesh!")
+        Terrain::from_models(path, models)
+    }
+
+    // Builds the terrain mesh from already parsed models, e.g. ones parsed on a loader thread.
+    // `path` is the file they were parsed from.
+    pub fn from_models(path: &str, models: Vec<tobj::Model>) -> Result<Mesh, String> {
+        if models.len() != 1 {
+            return Err(format!("{} has {} models, please use a model with a single mesh!", path, models.len()));
             // You could try merging the vertices and indices
             // of the separate meshes into a single mesh.
             // I'll leave that as an optional exercise. ;)
@@ -61,7 +175,7 @@ impl Terrain {
             terrain.mesh.indices.len() / 3,
         );
 
-        Mesh::from(terrain.mesh, [1.0, 1.0, 1.0, 1.0])
+        Ok(MeshRef { file: path.to_string(), model: None, color: [1.0, 1.0, 1.0, 1.0], occlusion: None }.into_mesh(terrain))
     }
 }
 
@@ -91,34 +205,37 @@ impl Index<usize> for Helicopter {
 }
 
 impl Helicopter {
-    pub fn load(path: &str) -> Self {
+    #[allow(dead_code)]
+    pub fn load(path: &str) -> Result<Self, String> {
         println!("Loading helicopter model...");
         let before = std::time::Instant::now();
-        let (models, _materials)
-            = tobj::load_obj(path,
-                &tobj::LoadOptions{
-                    triangulate: true,
-                    single_index: true,
-                    ..Default::default()
-                }
-            ).expect("Failed to load helicopter model");
+        let (models, _materials) = tobj::load_obj(path, &load_options())
+            .map_err(|e| format!("Failed to load helicopter model {}: {}", path, e))?;
         let after = std::time::Instant::now();
         println!("Done in {:.3}ms!", after.duration_since(before).as_micros() as f32 / 1e3);
 
+        Helicopter::from_models(path, models)
+    }
+
+    // Builds the helicopter meshes from already parsed models, e.g. ones parsed on a loader thread.
+    // `path` is the file they were parsed from.
+    pub fn from_models(path: &str, models: Vec<tobj::Model>) -> Result<Self, String> {
         for model in &models {
             println!("Loaded {} with {} points and {} triangles.", model.name, model.mesh.positions.len() / 3, model.mesh.indices.len() / 3);
         }
 
-        let body_model = models.iter().find(|m| m.name == "Body_body").expect("Incorrect model file!").to_owned();
-        let door_model = models.iter().find(|m| m.name == "Door_door").expect("Incorrect model file!").to_owned();
-        let main_rotor_model = models.iter().find(|m| m.name == "Main_Rotor_main_rotor").expect("Incorrect model file!").to_owned();
-        let tail_rotor_model = models.iter().find(|m| m.name == "Tail_Rotor_tail_rotor").expect("Incorrect model file!").to_owned();
-
-        Helicopter {
-            body:       Mesh::from(body_model.mesh,         [0.3, 0.3, 0.3, 1.0]),
-            door:       Mesh::from(door_model.mesh,         [0.1, 0.1, 0.3, 1.0]),
-            main_rotor: Mesh::from(main_rotor_model.mesh,   [0.3, 0.1, 0.1, 1.0]),
-            tail_rotor: Mesh::from(tail_rotor_model.mesh,   [0.1, 0.3, 0.1, 1.0]),
-        }
+        let part = |name: &str, color: [f32; 4]| -> Result<Mesh, String> {
+            let model = models.iter().find(|m| m.name == name)
+                .ok_or_else(|| format!("Incorrect model file! {} has no model called {}", path, name))?
+                .to_owned();
+            Ok(MeshRef { file: path.to_string(), model: Some(name.to_string()), color, occlusion: None }.into_mesh(model))
+        };
+
+        Ok(Helicopter {
+            body:       part("Body_body",               [0.3, 0.3, 0.3, 1.0])?,
+            door:       part("Door_door",               [0.1, 0.1, 0.3, 1.0])?,
+            main_rotor: part("Main_Rotor_main_rotor",   [0.3, 0.1, 0.1, 1.0])?,
+            tail_rotor: part("Tail_Rotor_tail_rotor",   [0.1, 0.3, 0.1, 1.0])?,
+        })
     }
 }
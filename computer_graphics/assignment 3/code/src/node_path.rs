@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use crate::scene_graph::{NodeId, Scene};
+
+// Finding nodes by name. A path is the names of the nodes from just below the root down to the
+// node, separated by slashes, e.g. "lunar_surface/helicopter_3/main_rotor". The root itself is the
+// empty path. Names do not have to be unique; where several siblings share a name, `lookup` takes
+// the first one.
+//
+// `query` accepts wildcards in the path:
+//  * `*` matches any run of characters within a name, `?` any single character
+//  * a `**` segment matches any number of levels, including none
+// so "lunar_surface/*/main_rotor" finds every main rotor, and "**/door" every door in the scene.
+
+impl Scene {
+    // The first node with this name, depth first from the root
+    #[allow(dead_code)]
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.depth_first(self.root()).map(|visit| visit.id).find(|&id| self[id].name == name)
+    }
+
+    // Every node with this name, depth first from the root
+    #[allow(dead_code)]
+    pub fn find_all(&self, name: &str) -> Vec<NodeId> {
+        self.depth_first(self.root()).map(|visit| visit.id).filter(|&id| self[id].name == name).collect()
+    }
+
+    // The node at exactly this path, no wildcards
+    #[allow(dead_code)]
+    pub fn lookup(&self, path: &str) -> Option<NodeId> {
+        split_path(path).iter().try_fold(self.root(), |current, segment| {
+            self.children(current).iter().copied().find(|&child| self[child].name == *segment)
+        })
+    }
+
+    // Every node matching a path with wildcards, in depth first order
+    #[allow(dead_code)]
+    pub fn query(&self, pattern: &str) -> Vec<NodeId> {
+        let mut matches = HashSet::new();
+        self.query_below(self.root(), &split_path(pattern), &mut matches);
+        // `**` finds the nodes level by level rather than in depth first order, so sort them
+        self.depth_first(self.root()).map(|visit| visit.id).filter(|id| matches.contains(id)).collect()
+    }
+
+    // Several `**` can reach the same node along different routes, the set keeps it once
+    fn query_below(&self, id: NodeId, segments: &[&str], matches: &mut HashSet<NodeId>) {
+        let Some((&segment, rest)) = segments.split_first() else {
+            matches.insert(id);
+            return;
+        };
+
+        if segment == "**" {
+            self.query_below(id, rest, matches);
+            for &child in self.children(id) {
+                self.query_below(child, segments, matches);
+            }
+        } else {
+            for &child in self.children(id) {
+                if wildcard_match(segment.as_bytes(), self[child].name.as_bytes()) {
+                    self.query_below(child, rest, matches);
+                }
+            }
+        }
+    }
+
+    // The inverse of `lookup`, the names from below the root down to the node
+    pub fn path(&self, id: NodeId) -> String {
+        let mut names = vec![];
+        let mut current = id;
+        while let Some(parent) = self.parent(current) {
+            names.push(self[current].name.as_str());
+            current = parent;
+        }
+        names.reverse();
+        names.join("/")
+    }
+}
+
+// Empty segments are skipped, so leading, trailing and double slashes don't matter
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('/').filter(|segment| !segment.is_empty()).collect()
+}
+
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            wildcard_match(&pattern[1..], name) || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => wildcard_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
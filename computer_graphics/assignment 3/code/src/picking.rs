@@ -0,0 +1,63 @@
+extern crate nalgebra_glm as glm;
+
+use crate::geometry::Ray;
+use crate::scene_bvh::SceneBvh;
+use crate::scene_graph::{NodeId, Scene};
+
+// Mouse picking: finding the node under the cursor. The cursor is unprojected into a ray from the
+// near to the far plane of the camera, and the ray is tested against the node bounds in the scene
+// BVH first, and then against the triangles of every mesh whose bounds it passes through, nearest
+// bounds first. The triangles are tested in the mesh's own space, so nothing is transformed but
+// the ray.
+
+pub struct PickHit {
+    pub node     : NodeId,
+    pub position : glm::Vec3,  // Where the ray hit the mesh, in world space
+    pub distance : f32,        // From the near plane, in world units
+}
+
+// The ray through the cursor, at pixel `cursor` from the top left of a `screen_w` by `screen_h`
+// window, for a camera drawing with `view_projection_matrix`
+pub fn cursor_ray(cursor: (f32, f32), screen_w: u32, screen_h: u32, view_projection_matrix: &glm::Mat4) -> Ray {
+    // Normalized device coordinates have y pointing up, the window's pixels down
+    let x = 2.0 * cursor.0 / screen_w as f32 - 1.0;
+    let y = 1.0 - 2.0 * cursor.1 / screen_h as f32;
+    let inverse = glm::inverse(view_projection_matrix);
+    let unproject = |z: f32| {
+        let point = inverse * glm::vec4(x, y, z, 1.0);
+        point.xyz() / point.w
+    };
+    let near = unproject(-1.0);
+    let far = unproject(1.0);
+    Ray::new(near, glm::normalize(&(far - near)))
+}
+
+// The nearest node drawn in any of the layers in `layer_mask` whose mesh the ray hits before
+// `max_distance`. Hidden nodes are skipped. `bvh` must be up to date with the scene.
+pub fn pick(scene: &Scene, bvh: &SceneBvh, ray: &Ray, max_distance: f32, layer_mask: u32) -> Option<PickHit> {
+    let mut nearest: Option<PickHit> = None;
+    for (id, bounds_distance) in bvh.query_ray(ray, max_distance) {
+        // The rest of the candidates start further away than the nearest hit
+        let best = nearest.as_ref().map_or(max_distance, |hit| hit.distance);
+        if bounds_distance > best {
+            break;
+        }
+        let node = &scene[id];
+        let Some(mesh) = node.primary_mesh() else { continue };
+        if node.layers & layer_mask == 0 || !scene.is_visible(id) {
+            continue;
+        }
+
+        // Moving the ray into the mesh's space keeps distances along it the same, since they
+        // are measured in units of the (transformed) direction
+        let inverse_world = glm::inverse(&scene.world_matrix(id));
+        let local_ray = Ray::new(
+            (inverse_world * ray.origin.push(1.0)).xyz(),
+            (inverse_world * ray.direction.push(0.0)).xyz(),
+        );
+        if let Some(hit) = mesh.triangles.closest_hit(&local_ray, best) {
+            nearest = Some(PickHit { node: id, position: ray.at(hit.distance), distance: hit.distance });
+        }
+    }
+    nearest
+}
@@ -0,0 +1,98 @@
+extern crate nalgebra_glm as glm;
+
+use serde::{Deserialize, Serialize};
+
+// Conversions between Euler angles, rotation matrices and quaternions.
+//
+// Euler angles are always stored as (angle around X, angle around Y, angle around Z). The order
+// says in which order the three rotation matrices are multiplied, so `EulerOrder::XYZ` means
+// Rx * Ry * Rz, just like calling `glm::rotate_x`, `glm::rotate_y` and `glm::rotate_z` in turn.
+// The first axis is the outermost one: for a vehicle, YXZ gives yaw, then pitch, then roll.
+
+#[allow(dead_code)]
+#[allow(clippy::upper_case_acronyms)]  // The axes in order, as they are usually written
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum EulerOrder {
+    XYZ,
+    XZY,
+    YXZ,
+    YZX,
+    ZXY,
+    ZYX,
+}
+
+impl EulerOrder {
+    // The axes (0 = X, 1 = Y, 2 = Z) in multiplication order
+    pub fn axes(self) -> [usize; 3] {
+        match self {
+            EulerOrder::XYZ => [0, 1, 2],
+            EulerOrder::XZY => [0, 2, 1],
+            EulerOrder::YXZ => [1, 0, 2],
+            EulerOrder::YZX => [1, 2, 0],
+            EulerOrder::ZXY => [2, 0, 1],
+            EulerOrder::ZYX => [2, 1, 0],
+        }
+    }
+}
+
+fn axis_vector(axis: usize) -> glm::Vec3 {
+    let mut vector: glm::Vec3 = glm::zero();
+    vector[axis] = 1.0;
+    vector
+}
+
+pub fn euler_to_quat(angles: &glm::Vec3, order: EulerOrder) -> glm::Quat {
+    order.axes().iter().fold(glm::quat_identity(), |rotation, &axis| {
+        rotation * glm::quat_angle_axis(angles[axis], &axis_vector(axis))
+    })
+}
+
+pub fn euler_to_mat4(angles: &glm::Vec3, order: EulerOrder) -> glm::Mat4 {
+    order.axes().iter().fold(glm::identity(), |rotation, &axis| {
+        glm::rotate(&rotation, angles[axis], &axis_vector(axis))
+    })
+}
+
+pub fn quat_to_euler(rotation: &glm::Quat, order: EulerOrder) -> glm::Vec3 {
+    euler_from_matrix(&glm::quat_to_mat3(&glm::quat_normalize(rotation)), order)
+}
+
+// Inverse of `euler_to_mat4` for a pure rotation matrix. In gimbal lock the first and last axes
+// turn about the same axis, and all of that rotation is put on the first one.
+pub fn euler_from_matrix(rotation: &glm::Mat3, order: EulerOrder) -> glm::Vec3 {
+    let [i, j, k] = order.axes();
+    // Even permutations of XYZ have the same signs as XYZ itself, odd ones are mirrored
+    let sign = if (j + 3 - i) % 3 == 1 { 1.0 } else { -1.0 };
+
+    let sin_middle = (sign * rotation[(i, k)]).clamp(-1.0, 1.0);
+    let cos_middle = (rotation[(i, i)].powi(2) + rotation[(i, j)].powi(2)).sqrt();
+
+    let mut angles: glm::Vec3 = glm::zero();
+    angles[j] = sin_middle.atan2(cos_middle);
+    if cos_middle > 1e-6 {
+        angles[i] = (-sign * rotation[(j, k)]).atan2(rotation[(k, k)]);
+        angles[k] = (-sign * rotation[(i, j)]).atan2(rotation[(i, i)]);
+    } else {
+        angles[i] = (sign * rotation[(k, j)]).atan2(rotation[(j, j)]);
+    }
+    angles
+}
+
+// The rotation that turns an object's forward axis (-Z, as for OpenGL cameras) towards
+// `direction`, keeping its up axis (+Y) as close to `up` as possible
+pub fn look_rotation(direction: &glm::Vec3, up: &glm::Vec3) -> glm::Quat {
+    let forward = glm::normalize(direction);
+    let mut right = glm::cross(&forward, up);
+    if glm::length(&right) < 1e-6 {
+        // Looking straight along `up`, any right vector will do
+        right = glm::cross(&forward, &axis_vector(if forward.x.abs() < 0.9 { 0 } else { 2 }));
+    }
+    let right = glm::normalize(&right);
+    let true_up = glm::cross(&right, &forward);
+    let basis = glm::mat3(
+        right.x, true_up.x, -forward.x,
+        right.y, true_up.y, -forward.y,
+        right.z, true_up.z, -forward.z,
+    );
+    glm::mat3_to_quat(&basis)
+}
@@ -0,0 +1,258 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::geometry::{Aabb, Frustum, Ray};
+use crate::scene_graph::{NodeId, Scene};
+
+// A bounding volume hierarchy over the nodes of a scene, by the world space bounds of their
+// meshes, for finding what is near a point, a box or a ray without testing every node. Unlike
+// `TriangleBvh` it is dynamic: `update` follows the scene as nodes move, appear and disappear.
+//
+// Every node with a mesh is a leaf. Leaves store their bounds grown by a margin, so a node that
+// only moves a little stays inside its box and the tree is left alone. A node that leaves its box
+// is taken out and inserted again, and the boxes above it are refitted on the way. Queries test
+// the exact bounds at the leaves, so the margins never show in the results.
+//
+// Hidden nodes and nodes in any layer are all in the tree, filter the results if that matters.
+
+// How far a leaf's box reaches past its node's bounds, relative to the size of the bounds
+const MARGIN: f32 = 0.1;
+
+const NONE: usize = usize::MAX;
+
+struct TreeNode {
+    fat_bounds : Aabb,            // Leaves: the node's bounds plus the margin. Inner nodes: both children's
+    bounds     : Aabb,            // Leaves: the node's exact bounds
+    parent     : usize,           // NONE at the root of the tree
+    children   : [usize; 2],      // NONE for leaves
+    node_id    : Option<NodeId>,  // Leaves: the scene node
+}
+
+impl TreeNode {
+    fn is_leaf(&self) -> bool {
+        self.children[0] == NONE
+    }
+}
+
+pub struct SceneBvh {
+    nodes  : Vec<TreeNode>,
+    free   : Vec<usize>,  // Indices of unused tree nodes, reused before the tree grows
+    root   : usize,
+    leaves : HashMap<NodeId, usize>,
+}
+
+impl SceneBvh {
+    pub fn new() -> SceneBvh {
+        SceneBvh { nodes: vec![], free: vec![], root: NONE, leaves: HashMap::new() }
+    }
+
+    // Brings the tree up to date with every node with a mesh in the scene, by the world matrices
+    // from the scene's cache
+    pub fn update(&mut self, scene: &Scene) {
+        let mut seen = HashSet::new();
+        for visit in scene.depth_first(scene.root()) {
+            let Some(mesh) = visit.node.primary_mesh() else { continue };
+            seen.insert(visit.id);
+            self.set(visit.id, mesh.bounds.transformed(&visit.world_matrix));
+        }
+
+        // Removed nodes, and nodes that lost their mesh
+        let gone: Vec<NodeId> = self.leaves.keys().copied().filter(|id| !seen.contains(id)).collect();
+        for id in gone {
+            self.remove(id);
+        }
+    }
+
+    // Inserts the node, or moves it if it is already in the tree
+    pub fn set(&mut self, id: NodeId, bounds: Aabb) {
+        if let Some(&leaf) = self.leaves.get(&id) {
+            if self.nodes[leaf].fat_bounds.contains(&bounds) {
+                self.nodes[leaf].bounds = bounds;
+                return;
+            }
+            self.remove(id);
+        }
+
+        let margin = MARGIN * glm::comp_max(&bounds.extent()).max(0.0);
+        let leaf = self.allocate(TreeNode {
+            fat_bounds: bounds.expanded(margin),
+            bounds,
+            parent: NONE,
+            children: [NONE; 2],
+            node_id: Some(id),
+        });
+        self.leaves.insert(id, leaf);
+        self.insert_leaf(leaf);
+    }
+
+    pub fn remove(&mut self, id: NodeId) {
+        let Some(leaf) = self.leaves.remove(&id) else { return };
+        self.remove_leaf(leaf);
+        self.free.push(leaf);
+    }
+
+    // The exact bounds the node was last given
+    #[allow(dead_code)]
+    pub fn bounds(&self, id: NodeId) -> Option<Aabb> {
+        self.leaves.get(&id).map(|&leaf| self.nodes[leaf].bounds)
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    fn allocate(&mut self, node: TreeNode) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NONE {
+            self.root = leaf;
+            return;
+        }
+
+        // Walk down to the sibling that makes the tree's boxes grow the least (the surface area
+        // heuristic), stopping early when pairing with the current node is cheapest
+        let leaf_bounds = self.nodes[leaf].fat_bounds;
+        let mut sibling = self.root;
+        while !self.nodes[sibling].is_leaf() {
+            let node = &self.nodes[sibling];
+            let combined = node.fat_bounds.merge(&leaf_bounds).surface_area();
+            let cost_here = 2.0 * combined;
+            // What every node on the way down grows by, whichever child is picked
+            let inherited = 2.0 * (combined - node.fat_bounds.surface_area());
+            let cost = |child: usize| {
+                let child = &self.nodes[child];
+                let merged = child.fat_bounds.merge(&leaf_bounds).surface_area();
+                let growth = if child.is_leaf() { merged } else { merged - child.fat_bounds.surface_area() };
+                growth + inherited
+            };
+            let [left, right] = node.children;
+            let (cost_left, cost_right) = (cost(left), cost(right));
+            if cost_here < cost_left && cost_here < cost_right {
+                break;
+            }
+            sibling = if cost_left < cost_right { left } else { right };
+        }
+
+        // A new inner node takes the sibling's place, with the sibling and the leaf below it
+        let old_parent = self.nodes[sibling].parent;
+        let new_parent = self.allocate(TreeNode {
+            fat_bounds: self.nodes[sibling].fat_bounds.merge(&leaf_bounds),
+            bounds: Aabb::empty(),
+            parent: old_parent,
+            children: [sibling, leaf],
+            node_id: None,
+        });
+        self.nodes[sibling].parent = new_parent;
+        self.nodes[leaf].parent = new_parent;
+        if old_parent == NONE {
+            self.root = new_parent;
+        } else {
+            self.replace_child(old_parent, sibling, new_parent);
+        }
+        self.refit(old_parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        let parent = self.nodes[leaf].parent;
+        if parent == NONE {
+            self.root = NONE;
+            return;
+        }
+
+        // The sibling takes the parent's place
+        let [left, right] = self.nodes[parent].children;
+        let sibling = if left == leaf { right } else { left };
+        let grandparent = self.nodes[parent].parent;
+        self.nodes[sibling].parent = grandparent;
+        if grandparent == NONE {
+            self.root = sibling;
+        } else {
+            self.replace_child(grandparent, parent, sibling);
+        }
+        self.free.push(parent);
+        self.refit(grandparent);
+    }
+
+    fn replace_child(&mut self, parent: usize, old_child: usize, new_child: usize) {
+        for child in &mut self.nodes[parent].children {
+            if *child == old_child {
+                *child = new_child;
+            }
+        }
+    }
+
+    // Recomputes the boxes of `index` and everything above it from their children
+    fn refit(&mut self, mut index: usize) {
+        while index != NONE {
+            let [left, right] = self.nodes[index].children;
+            self.nodes[index].fat_bounds = self.nodes[left].fat_bounds.merge(&self.nodes[right].fat_bounds);
+            index = self.nodes[index].parent;
+        }
+    }
+
+    // Every node whose exact bounds pass `test`, where `test` must also pass for any box around them
+    fn query<F: Fn(&Aabb) -> bool>(&self, test: F) -> Vec<NodeId> {
+        let mut found = vec![];
+        let mut stack = if self.root == NONE { vec![] } else { vec![self.root] };
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !test(&node.fat_bounds) {
+                continue;
+            }
+            if node.is_leaf() {
+                if test(&node.bounds) {
+                    found.extend(node.node_id);
+                }
+            } else {
+                stack.extend_from_slice(&node.children);
+            }
+        }
+        found
+    }
+
+    // The nodes whose bounds overlap the box
+    #[allow(dead_code)]
+    pub fn query_aabb(&self, aabb: &Aabb) -> Vec<NodeId> {
+        self.query(|bounds| bounds.intersects(aabb))
+    }
+
+    // The nodes whose bounds overlap the sphere
+    #[allow(dead_code)]
+    pub fn query_sphere(&self, center: &glm::Vec3, radius: f32) -> Vec<NodeId> {
+        self.query(|bounds| bounds.intersects_sphere(center, radius))
+    }
+
+    // The nodes whose bounds may be inside the frustum, see `Frustum::intersects_aabb`
+    #[allow(dead_code)]
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<NodeId> {
+        self.query(|bounds| frustum.intersects_aabb(bounds))
+    }
+
+    // The nodes whose bounds the ray enters before `max_distance`, nearest first, with the
+    // distance at which it enters them
+    pub fn query_ray(&self, ray: &Ray, max_distance: f32) -> Vec<(NodeId, f32)> {
+        let mut hits: Vec<(NodeId, f32)> = self
+            .query(|bounds| bounds.intersect_ray(ray, max_distance).is_some())
+            .into_iter()
+            .filter_map(|id| {
+                let bounds = self.nodes[self.leaves[&id]].bounds;
+                bounds.intersect_ray(ray, max_distance).map(|distance| (id, distance))
+            })
+            .collect();
+        hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        hits
+    }
+}
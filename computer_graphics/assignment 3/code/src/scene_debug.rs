@@ -0,0 +1,163 @@
+extern crate nalgebra_glm as glm;
+
+use std::fmt::Write;
+
+use crate::scene_graph::{NodeId, Scene, SceneNode};
+use crate::traversal::{SceneVisitor, Visit};
+
+// Looking at a whole scene graph at once, for debugging parenting: a text tree for the terminal
+// and a Graphviz graph (render it with `dot -Tsvg scene.dot -o scene.svg`). Next to the local
+// transform, every node shows where its origin and its pivot (the reference point it rotates and
+// scales about) end up in the world, which is what goes wrong when a part spins around the wrong
+// point.
+
+impl Scene {
+    // The subtree below `start` as an indented tree, one node and its transforms per entry
+    pub fn dump_tree(&self, start: NodeId) -> String {
+        let mut printer = TreePrinter { text: String::new(), last_child: vec![] };
+        self.walk(start, &mut printer);
+        printer.text
+    }
+
+    pub fn print_tree(&self, start: NodeId) {
+        print!("{}", self.dump_tree(start));
+    }
+
+    // The subtree below `start` as a Graphviz digraph
+    pub fn to_dot(&self, start: NodeId) -> String {
+        let mut exporter = DotExporter { text: String::new(), count: 0, ancestors: vec![] };
+        writeln!(exporter.text, "digraph scene {{").unwrap();
+        writeln!(exporter.text, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        self.walk(start, &mut exporter);
+        writeln!(exporter.text, "}}").unwrap();
+        exporter.text
+    }
+
+    pub fn write_dot(&self, start: NodeId, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_dot(start))
+    }
+}
+
+fn display_name(scene: &Scene, visit: &Visit) -> String {
+    if visit.id == scene.root() {
+        "(root)".to_string()
+    } else if visit.node.name.is_empty() {
+        "(unnamed)".to_string()
+    } else {
+        visit.node.name.clone()
+    }
+}
+
+fn format_vec3(v: &glm::Vec3) -> String {
+    format!("({:.2}, {:.2}, {:.2})", v.x, v.y, v.z)
+}
+
+fn transform_lines(node: &SceneNode, world_matrix: &glm::Mat4) -> Vec<String> {
+    let rotation = match node.orientation {
+        Some(q) => format!("quaternion ({:.3}, {:.3}, {:.3}, {:.3})", q.i, q.j, q.k, q.w),
+        None => format!("{} {:?}", format_vec3(&node.rotation), node.rotation_order),
+    };
+    let origin = world_matrix * glm::vec4(0.0, 0.0, 0.0, 1.0);
+    let pivot = world_matrix * node.reference_point.push(1.0);
+    let mut lines = vec![];
+    if let Some(mesh) = node.primary_mesh() {
+        lines.push(format!("VAO {}, {} indices, layers {:#x}", mesh.vao_id, mesh.index_count, node.layers));
+    }
+    if let Some(lod) = &node.lod {
+        lines.push(format!("{} levels of detail by {:?}, showing level {}", lod.levels.len(), lod.metric, lod.current_level()));
+    }
+    if let Some(material) = &node.material {
+        lines.push(format!("material: {:?}", material));
+    }
+    if let Some(camera) = &node.camera {
+        lines.push(format!("camera: {:?}", camera));
+    }
+    if let Some(light) = &node.light {
+        lines.push(format!("light: {:?}", light));
+    }
+    if !node.visible {
+        lines.push("hidden, and so is everything below".to_string());
+    }
+    lines.push(format!("local: position {} rotation {} scale {} pivot {}",
+        format_vec3(&node.position),
+        rotation,
+        format_vec3(&node.scale),
+        format_vec3(&node.reference_point),
+    ));
+    lines.push(format!("world: origin {} pivot {}", format_vec3(&origin.xyz()), format_vec3(&pivot.xyz())));
+    lines
+}
+
+// Whether the node is the last of its parent's children, which decides the tree's branch lines
+fn is_last_child(scene: &Scene, id: NodeId) -> bool {
+    scene.parent(id).is_none_or(|parent| scene.children(parent).last() == Some(&id))
+}
+
+struct TreePrinter {
+    text       : String,
+    last_child : Vec<bool>,  // For every level from the start down to the current node
+}
+
+impl SceneVisitor for TreePrinter {
+    fn enter(&mut self, scene: &Scene, visit: &Visit) -> bool {
+        // The start of the traversal is drawn without a branch, like the root
+        let is_last = visit.depth == 0 || is_last_child(scene, visit.id);
+        let mut indent = String::new();
+        for &ancestor_is_last in self.last_child.iter().skip(1) {
+            indent += if ancestor_is_last { "    " } else { "│   " };
+        }
+
+        let branch = if visit.depth == 0 { "" } else if is_last { "└── " } else { "├── " };
+        writeln!(self.text, "{}{}{}", indent, branch, display_name(scene, visit)).unwrap();
+
+        // The details hang below the name, continuing the parent's line if more siblings follow
+        if visit.depth > 0 {
+            indent += if is_last { "    " } else { "│   " };
+        }
+        let continues = if visit.node.children().is_empty() { "    " } else { "│   " };
+        for line in transform_lines(visit.node, &visit.world_matrix) {
+            writeln!(self.text, "{}{}{}", indent, continues, line).unwrap();
+        }
+
+        self.last_child.push(is_last);
+        true
+    }
+
+    fn leave(&mut self, _scene: &Scene, _visit: &Visit) {
+        self.last_child.pop();
+    }
+}
+
+struct DotExporter {
+    text      : String,
+    count     : usize,       // Graphviz node ids are handed out in traversal order
+    ancestors : Vec<usize>,  // The Graphviz ids from the start down to the current node
+}
+
+impl SceneVisitor for DotExporter {
+    fn enter(&mut self, scene: &Scene, visit: &Visit) -> bool {
+        let id = self.count;
+        self.count += 1;
+
+        let mut label = escape_dot(&display_name(scene, visit));
+        for line in transform_lines(visit.node, &visit.world_matrix) {
+            label += "\\n";
+            label += &escape_dot(&line);
+        }
+        writeln!(self.text, "    n{} [label=\"{}\"];", id, label).unwrap();
+        if let Some(parent) = self.ancestors.last() {
+            writeln!(self.text, "    n{} -> n{};", parent, id).unwrap();
+        }
+
+        self.ancestors.push(id);
+        true
+    }
+
+    fn leave(&mut self, _scene: &Scene, _visit: &Visit) {
+        self.ancestors.pop();
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,242 @@
+extern crate nalgebra_glm as glm;
+
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+use crate::gpu_mesh::GpuMesh;
+use crate::light::{Light, LightKind};
+use crate::material::Material;
+use crate::mesh::MeshRef;
+use crate::rotation::EulerOrder;
+use crate::scene_graph::{NodeId, Scene, SceneNode};
+
+// Saving and loading the node hierarchy as RON, a text format that reads a lot like Rust:
+//
+//     (
+//         nodes: [
+//             (
+//                 name: "lunar_surface",
+//                 mesh: Some((
+//                     file: "./resources/lunarsurface.obj",
+//                     model: None,
+//                     color: (1.0, 1.0, 1.0, 1.0),
+//                 )),
+//                 children: [
+//                     (
+//                         name: "helicopter_1",
+//                         position: (0.0, 0.0, 50.0),
+//                         rotation_order: YXZ,
+//                         children: [...],
+//                     ),
+//                     (
+//                         name: "lamp",
+//                         position: (0.0, 5.0, 0.0),
+//                         light: Some((
+//                             kind: Point(range: 20.0),
+//                             color: (1.0, 0.9, 0.7),
+//                             intensity: 1.0,
+//                         )),
+//                     ),
+//                 ],
+//             ),
+//         ],
+//     )
+//
+// Meshes are stored as references to the models they were loaded from (and the ambient occlusion
+// baked into them), not as geometry, and are uploaded again when the file is instantiated into a
+// scene. Anything left out of a node gets the same default as `SceneNode::new()`. Of the node's
+// material only the tint and emissive colors are kept, shader programs only exist while the
+// program runs. Behaviours are code and are not saved, and nodes with levels of detail are saved
+// with just their finest mesh.
+
+#[derive(Serialize, Deserialize)]
+pub struct SceneFile {
+    pub nodes : Vec<NodeDescription>,  // The children of the root, the root itself is not stored
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeDescription {
+    pub name            : String,
+    pub position        : [f32; 3],
+    pub rotation        : [f32; 3],
+    pub rotation_order  : EulerOrder,
+    pub orientation     : Option<[f32; 4]>,  // Quaternion as (x, y, z, w)
+    pub scale           : [f32; 3],
+    pub reference_point : [f32; 3],
+    pub mesh            : Option<MeshRef>,
+    pub visible         : bool,
+    pub layers          : u32,
+    pub tint            : Option<[f32; 4]>,  // From the node's material
+    pub emissive        : Option<[f32; 3]>,  // From the node's material
+    pub camera          : Option<Camera>,
+    pub light           : Option<LightDescription>,
+    pub children        : Vec<NodeDescription>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LightDescription {
+    pub kind      : LightKind,
+    pub color     : [f32; 3],
+    pub intensity : f32,
+}
+
+impl Default for NodeDescription {
+    fn default() -> NodeDescription {
+        NodeDescription::from_node(&SceneNode::new())
+    }
+}
+
+impl NodeDescription {
+    // Just the node, without its children
+    fn from_node(node: &SceneNode) -> NodeDescription {
+        NodeDescription {
+            name: node.name.clone(),
+            position: node.position.into(),
+            rotation: node.rotation.into(),
+            rotation_order: node.rotation_order,
+            orientation: node.orientation.map(|q| [q.i, q.j, q.k, q.w]),
+            scale: node.scale.into(),
+            reference_point: node.reference_point.into(),
+            mesh: node.primary_mesh().and_then(|mesh| mesh.source.clone()),
+            visible: node.visible,
+            layers: node.layers,
+            tint: node.material.as_ref().and_then(|material| material.tint).map(Into::into),
+            emissive: node.material.as_ref().and_then(|material| material.emissive).map(Into::into),
+            camera: node.camera,
+            light: node.light.map(|light| LightDescription {
+                kind: light.kind,
+                color: light.color.into(),
+                intensity: light.intensity,
+            }),
+            children: vec![],
+        }
+    }
+
+    pub fn from_scene(scene: &Scene, id: NodeId) -> NodeDescription {
+        NodeDescription {
+            children: scene.children(id).iter().map(|&child| NodeDescription::from_scene(scene, child)).collect(),
+            ..NodeDescription::from_node(&scene[id])
+        }
+    }
+
+    // Adds the node and its children to the scene below `parent`. `upload` gives the GPU mesh to
+    // draw for a mesh reference, so it decides which nodes share one. If a mesh fails to load,
+    // nothing is added.
+    pub fn instantiate(
+        &self,
+        scene: &mut Scene,
+        parent: NodeId,
+        upload: &mut dyn FnMut(&MeshRef) -> Result<Rc<GpuMesh>, String>,
+    ) -> Result<NodeId, String> {
+        let mut node = match &self.mesh {
+            Some(mesh) => SceneNode::from_mesh(upload(mesh)?),
+            None => SceneNode::new(),
+        };
+        node.name = self.name.clone();
+        node.position = glm::make_vec3(&self.position);
+        node.rotation = glm::make_vec3(&self.rotation);
+        node.rotation_order = self.rotation_order;
+        node.orientation = self.orientation.map(|[x, y, z, w]| glm::quat(x, y, z, w));
+        node.scale = glm::make_vec3(&self.scale);
+        node.reference_point = glm::make_vec3(&self.reference_point);
+        node.visible = self.visible;
+        node.layers = self.layers;
+        if self.tint.is_some() || self.emissive.is_some() {
+            node.material = Some(Material {
+                tint: self.tint.map(|tint| glm::make_vec4(&tint)),
+                emissive: self.emissive.map(|emissive| glm::make_vec3(&emissive)),
+                ..Material::default()
+            });
+        }
+        node.camera = self.camera;
+        node.light = self.light.as_ref().map(|light| {
+            Light::new(light.kind, glm::make_vec3(&light.color), light.intensity)
+        });
+
+        let id = scene.add_child(parent, node);
+        for child in &self.children {
+            if let Err(message) = child.instantiate(scene, id, upload) {
+                scene.remove(id);
+                return Err(message);
+            }
+        }
+        Ok(id)
+    }
+
+    // Leaves out the camera and the light wherever the scene node at the same path, `existing`,
+    // already has one
+    fn drop_duplicate_cameras_and_lights(&mut self, scene: &Scene, existing: Option<NodeId>) {
+        if let Some(id) = existing {
+            if scene[id].camera.is_some() {
+                self.camera = None;
+            }
+            if scene[id].light.is_some() {
+                self.light = None;
+            }
+        }
+        for child in &mut self.children {
+            let existing_child = existing.and_then(|id| child_named(scene, id, &child.name));
+            child.drop_duplicate_cameras_and_lights(scene, existing_child);
+        }
+    }
+}
+
+// The first child of the node with this name, as `Scene::lookup` takes it
+fn child_named(scene: &Scene, parent: NodeId, name: &str) -> Option<NodeId> {
+    scene.children(parent).iter().copied().find(|&child| scene[child].name == name)
+}
+
+impl SceneFile {
+    pub fn from_scene(scene: &Scene) -> SceneFile {
+        SceneFile {
+            nodes: scene.children(scene.root()).iter().map(|&id| NodeDescription::from_scene(scene, id)).collect(),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("Failed to serialize the scene: {}", e))?;
+        std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<SceneFile, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        ron::from_str(&text).map_err(|e| format!("Failed to parse {}: {}", path, e))
+    }
+
+    // For adding a file saved from the scene back into it: the nodes already there keep viewing
+    // and lighting it, and their copies from the file become plain nodes. Otherwise every light
+    // would shine twice as bright and every camera would be cycled through twice.
+    pub fn drop_duplicate_cameras_and_lights(&mut self, scene: &Scene, parent: NodeId) {
+        for node in &mut self.nodes {
+            let existing = child_named(scene, parent, &node.name);
+            node.drop_duplicate_cameras_and_lights(scene, existing);
+        }
+    }
+
+    // Adds every node in the file below `parent`, returning the new top level nodes. If a mesh
+    // fails to load, nothing is added.
+    pub fn instantiate(
+        &self,
+        scene: &mut Scene,
+        parent: NodeId,
+        upload: &mut dyn FnMut(&MeshRef) -> Result<Rc<GpuMesh>, String>,
+    ) -> Result<Vec<NodeId>, String> {
+        let mut added = vec![];
+        for node in &self.nodes {
+            match node.instantiate(scene, parent, upload) {
+                Ok(id) => added.push(id),
+                Err(message) => {
+                    for id in added {
+                        scene.remove(id);
+                    }
+                    return Err(message);
+                }
+            }
+        }
+        Ok(added)
+    }
+}
@@ -1,68 +1,312 @@
 extern crate nalgebra_glm as glm;
 
-use std::mem::ManuallyDrop;
-use std::pin::Pin;
-
-// Used to create an unholy abomination upon which you should not cast your gaze. This ended up
-// being a necessity due to wanting to keep the code written by students as "straight forward" as
-// possible. It is very very double plus ungood Rust, and intentionally leaks memory like a sieve.
-// But it works, and you're more than welcome to pretend it doesn't exist! In case you're curious
-// about how it works: It allocates memory on the heap (Box), promises to prevent it from being
-// moved or deallocated until dropped (Pin) and finally prevents the compiler from dropping it
-// automatically at all (ManuallyDrop).
-// ...
-// If that sounds like a janky solution, it's because it is!
-// Prettier, Rustier and better solutions were tried numerous times, but were all found wanting of
-// having what I arbitrarily decided to be the required level of "simplicity of use".
-pub type Node = ManuallyDrop<Pin<Box<SceneNode>>>;
+use std::cell::Cell;
+use std::ops::{Index, IndexMut};
+use std::rc::Rc;
+
+use crate::behaviour::Behaviour;
+use crate::camera::Camera;
+use crate::geometry::Aabb;
+use crate::gpu_mesh::GpuMesh;
+use crate::light::Light;
+use crate::lod::Lod;
+use crate::material::Material;
+use crate::rotation::{self, EulerOrder};
+
+// The scene graph is an arena: a `Scene` owns every node, and nodes refer to each other through
+// `NodeId` handles instead of pointers. A handle carries the generation of the slot it was given
+// out for, so a handle to a removed node is simply stale (`get` returns `None`) rather than
+// pointing at whatever node took its place.
+//
+// Local and world matrices are cached on the nodes. Any mutable access to a node through the
+// scene (`get_mut` or `scene[id]`) counts as a change: the node's local matrix and the world
+// matrices of its whole subtree are marked dirty, and recomputed the next time they are asked for.
+// Nodes nobody touches, like the terrain, are never recomputed.
+//
+// The same goes for the world space bounds of every subtree's meshes (`subtree_bounds`), which
+// are also dirty above a changed node, since its ancestors' subtrees contain it.
+
+// Render layers are bits in a mask: a node is drawn by a pass when its layers and the pass's
+// layer mask have a bit in common. Nodes start out in the default layer only.
+pub const DEFAULT_LAYER : u32 = 1;
+pub const ALL_LAYERS    : u32 = !0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId {
+    index      : u32,
+    generation : u32,
+}
 
 pub struct SceneNode {
+    pub name            : String,      // How I can be found, see `Scene::lookup`
     pub position        : glm::Vec3,   // Where I should be in relation to my parent
     pub rotation        : glm::Vec3,   // How I should be rotated, around the X, the Y and the Z axes
+    pub rotation_order  : EulerOrder,  // In which order those rotations are applied
+    pub orientation     : Option<glm::Quat>, // Replaces `rotation` when set, never gimbal locks
     pub scale           : glm::Vec3,   // How I should be scaled
     pub reference_point : glm::Vec3,   // The point I shall rotate and scale about
 
-    pub vao_id      : u32,             // What I should draw
-    pub index_count : i32,             // How much of it there is to draw
+    pub mesh        : Option<Rc<GpuMesh>>, // What I should draw, shared with other nodes drawing it
+    pub lod         : Option<Lod>,     // Replaces `mesh` with the level of detail for my size on screen
+    pub visible     : bool,            // Whether I and everything below me are drawn
+    pub layers      : u32,             // The render layers I am drawn in, see `draw_scene`
+    pub material    : Option<Material>, // Overrides for how I and my children are drawn
+    pub behaviours  : Vec<Box<dyn Behaviour>>, // What I do every frame, see `Scene::update_behaviours`
+    pub camera      : Option<Camera>,  // Makes me a viewpoint, looking down my -Z axis
+    pub light       : Option<Light>,   // Makes me shine, along my -Z axis
 
-    pub children: Vec<*mut SceneNode>, // Those I command
+    parent   : Option<NodeId>,         // The one I answer to
+    children : Vec<NodeId>,            // Those I command
+
+    transform_cache : TransformCache,  // Maintained by the scene
+}
+
+struct TransformCache {
+    local        : Cell<glm::Mat4>,
+    world        : Cell<glm::Mat4>,
+    local_dirty  : Cell<bool>,
+    world_dirty  : Cell<bool>,   // If set, it is also set on every descendant
+    bounds       : Cell<Aabb>,   // Around the meshes of the node and its subtree, in world space
+    mesh_count   : Cell<usize>,  // Nodes with a mesh in the subtree, including the node
+    bounds_dirty : Cell<bool>,   // If set, it is also set on every ancestor
+}
+
+impl TransformCache {
+    fn dirty() -> TransformCache {
+        TransformCache {
+            local        : Cell::new(glm::identity()),
+            world        : Cell::new(glm::identity()),
+            local_dirty  : Cell::new(true),
+            world_dirty  : Cell::new(true),
+            bounds       : Cell::new(Aabb::empty()),
+            mesh_count   : Cell::new(0),
+            bounds_dirty : Cell::new(true),
+        }
+    }
 }
 
 impl SceneNode {
 
-    pub fn new() -> Node {
-        ManuallyDrop::new(Pin::new(Box::new(SceneNode {
+    pub fn new() -> SceneNode {
+        SceneNode {
+            name            : String::new(),
             position        : glm::zero(),
             rotation        : glm::zero(),
+            rotation_order  : EulerOrder::XYZ,
+            orientation     : None,
             scale           : glm::vec3(1.0, 1.0, 1.0),
             reference_point : glm::zero(),
-            vao_id          : 0,
-            index_count     : -1,
+            mesh            : None,
+            lod             : None,
+            visible         : true,
+            layers          : DEFAULT_LAYER,
+            material        : None,
+            behaviours      : vec![],
+            camera          : None,
+            light           : None,
+            parent          : None,
             children        : vec![],
-        })))
+            transform_cache : TransformCache::dirty(),
+        }
     }
 
-    pub fn from_vao(vao_id: u32, index_count: i32) -> Node {
-        ManuallyDrop::new(Pin::new(Box::new(SceneNode {
-            position        : glm::zero(),
-            rotation        : glm::zero(),
-            scale           : glm::vec3(1.0, 1.0, 1.0),
-            reference_point : glm::zero(),
-            vao_id,
-            index_count,
-            children: vec![],
-        })))
+    pub fn from_mesh(mesh: Rc<GpuMesh>) -> SceneNode {
+        SceneNode {
+            mesh: Some(mesh),
+            ..SceneNode::new()
+        }
+    }
+
+    // Builder style setters, for describing a node in one expression before adding it to a scene
+
+    pub fn from_lod(lod: Lod) -> SceneNode {
+        SceneNode {
+            lod: Some(lod),
+            ..SceneNode::new()
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> SceneNode {
+        self.name = name.to_string();
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_visible(mut self, visible: bool) -> SceneNode {
+        self.visible = visible;
+        self
+    }
+
+    pub fn with_layers(mut self, layers: u32) -> SceneNode {
+        self.layers = layers;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_material(mut self, material: Material) -> SceneNode {
+        self.material = Some(material);
+        self
+    }
+
+    pub fn with_behaviour<B: Behaviour + 'static>(mut self, behaviour: B) -> SceneNode {
+        self.behaviours.push(Box::new(behaviour));
+        self
+    }
+
+    pub fn with_camera(mut self, camera: Camera) -> SceneNode {
+        self.camera = Some(camera);
+        self
+    }
+
+    pub fn with_light(mut self, light: Light) -> SceneNode {
+        self.light = Some(light);
+        self
+    }
+
+    pub fn with_position(mut self, position: glm::Vec3) -> SceneNode {
+        self.position = position;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: glm::Vec3) -> SceneNode {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_rotation_order(mut self, rotation_order: EulerOrder) -> SceneNode {
+        self.rotation_order = rotation_order;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_orientation(mut self, orientation: glm::Quat) -> SceneNode {
+        self.orientation = Some(orientation);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_scale(mut self, scale: glm::Vec3) -> SceneNode {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_reference_point(mut self, reference_point: glm::Vec3) -> SceneNode {
+        self.reference_point = reference_point;
+        self
+    }
+
+    // The mesh that stands for me in bounds, picking and saving: my finest level of detail, or else
+    // my mesh
+    pub fn primary_mesh(&self) -> Option<&Rc<GpuMesh>> {
+        self.lod.as_ref().map(|lod| lod.finest()).or(self.mesh.as_ref())
+    }
+
+    // My transform relative to my parent, computed from scratch. `Scene::local_matrix` caches it.
+    pub fn local_matrix(&self) -> glm::Mat4 {
+        let mut matrix = glm::identity();
+        matrix = glm::translate(&matrix, &self.position);
+        matrix = glm::translate(&matrix, &self.reference_point);
+        matrix = glm::scale(&matrix, &self.scale);
+        matrix *= self.rotation_matrix();
+        matrix = glm::translate(&matrix, &-self.reference_point);
+        matrix
+    }
+
+    pub fn rotation_matrix(&self) -> glm::Mat4 {
+        match self.orientation {
+            Some(orientation) => glm::quat_to_mat4(&glm::quat_normalize(&orientation)),
+            None => rotation::euler_to_mat4(&self.rotation, self.rotation_order),
+        }
+    }
+
+    // My rotation as a quaternion, whichever way it is stored
+    pub fn rotation_quat(&self) -> glm::Quat {
+        match self.orientation {
+            Some(orientation) => glm::quat_normalize(&orientation),
+            None => rotation::euler_to_quat(&self.rotation, self.rotation_order),
+        }
+    }
+
+    // Sets the rotation, keeping whichever representation (quaternion or Euler angles in
+    // `rotation_order`) the node already uses
+    pub fn set_rotation_quat(&mut self, orientation: &glm::Quat) {
+        match self.orientation {
+            Some(_) => self.orientation = Some(glm::quat_normalize(orientation)),
+            None => self.rotation = rotation::quat_to_euler(orientation, self.rotation_order),
+        }
+    }
+
+    // Switch to quaternion orientation, starting from the current rotation
+    #[allow(dead_code)]
+    pub fn use_quaternion(&mut self) {
+        self.orientation = Some(self.rotation_quat());
     }
 
-    pub fn add_child(&mut self, child: &SceneNode) {
-        self.children.push(child as *const SceneNode as *mut SceneNode)
+    // Switch back to Euler angles in `order`, starting from the current rotation
+    #[allow(dead_code)]
+    pub fn use_euler(&mut self, order: EulerOrder) {
+        let orientation = self.rotation_quat();
+        self.orientation = None;
+        self.rotation_order = order;
+        self.set_rotation_quat(&orientation);
     }
 
     #[allow(dead_code)]
-    pub fn get_child(& mut self, index: usize) -> & mut SceneNode {
-        unsafe {
-            &mut (*self.children[index])
+    pub fn set_axis_angle(&mut self, axis: &glm::Vec3, angle: f32) {
+        self.set_rotation_quat(&glm::quat_angle_axis(angle, &glm::normalize(axis)));
+    }
+
+    // Rotates further around `axis`, given in my parent's space
+    pub fn rotate_around(&mut self, axis: &glm::Vec3, angle: f32) {
+        let orientation = glm::quat_angle_axis(angle, &glm::normalize(axis)) * self.rotation_quat();
+        self.set_rotation_quat(&orientation);
+    }
+
+    // Turns my forward axis (-Z) towards `target`, given in my parent's space, from the point I
+    // rotate about
+    pub fn look_at(&mut self, target: &glm::Vec3, up: &glm::Vec3) {
+        let direction = target - (self.position + self.reference_point);
+        if glm::length(&direction) > 0.0 {
+            self.set_rotation_quat(&rotation::look_rotation(&direction, up));
+        }
+    }
+
+    // Sets position, rotation and scale so that `local_matrix()` reproduces `matrix`, keeping the
+    // reference point. Shear (from non-uniform scaling above a rotated node) cannot be represented
+    // and is dropped.
+    pub fn set_local_matrix(&mut self, matrix: &glm::Mat4) {
+        // The upper 3x3 is scale * rotation, so every row is a row of the rotation, scaled
+        let linear = glm::mat4_to_mat3(matrix);
+        let mut scale = glm::vec3(
+            glm::length(&linear.row(0).transpose()),
+            glm::length(&linear.row(1).transpose()),
+            glm::length(&linear.row(2).transpose()),
+        );
+        if glm::determinant(&linear) < 0.0 {
+            scale.x = -scale.x; // A mirror image, put the flip in the scale
         }
+        let mut rotation = linear;
+        for row in 0..3 {
+            if scale[row] != 0.0 {
+                let normalized = rotation.row(row) / scale[row];
+                rotation.set_row(row, &normalized);
+            }
+        }
+
+        // matrix = T(position + reference) * S * R * T(-reference)
+        let translation = glm::vec3(matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)]);
+        self.position = translation - self.reference_point + linear * self.reference_point;
+        self.scale = scale;
+        self.set_rotation_quat(&glm::mat3_to_quat(&rotation));
+    }
+
+    #[allow(dead_code)]
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
     }
 
     #[allow(dead_code)]
@@ -74,6 +318,7 @@ impl SceneNode {
     pub fn print(&self) {
         println!(
 "SceneNode {{
+    Name:      {}
     VAO:       {}
     Indices:   {}
     Children:  {}
@@ -81,8 +326,9 @@ impl SceneNode {
     Rotation:  [{:.2}, {:.2}, {:.2}]
     Reference: [{:.2}, {:.2}, {:.2}]
 }}",
-            self.vao_id,
-            self.index_count,
+            self.name,
+            self.mesh.as_ref().map_or(0, |mesh| mesh.vao_id),
+            self.mesh.as_ref().map_or(0, |mesh| mesh.index_count),
             self.children.len(),
             self.position.x,
             self.position.y,
@@ -99,20 +345,353 @@ impl SceneNode {
 }
 
 
-// You can also use square brackets to access the children of a SceneNode
-use std::ops::{Index, IndexMut};
-impl Index<usize> for SceneNode {
-    type Output = SceneNode;
-    fn index(&self, index: usize) -> &SceneNode {
-        unsafe {
-            & *(self.children[index] as *const SceneNode)
+struct Slot {
+    generation : u32,
+    node       : Option<SceneNode>,
+}
+
+pub struct Scene {
+    slots : Vec<Slot>,
+    free  : Vec<u32>,    // Indices of empty slots, reused before the arena grows
+    root  : NodeId,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        let mut scene = Scene {
+            slots: vec![],
+            free: vec![],
+            root: NodeId { index: 0, generation: 0 },
+        };
+        scene.root = scene.insert(SceneNode::new());
+        scene
+    }
+
+    // The node everything else hangs off, it cannot be removed
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    fn insert(&mut self, node: SceneNode) -> NodeId {
+        node.transform_cache.local_dirty.set(true);
+        node.transform_cache.world_dirty.set(true);
+        node.transform_cache.bounds_dirty.set(true);
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.node = Some(node);
+                NodeId { index, generation: slot.generation }
+            }
+            None => {
+                self.slots.push(Slot { generation: 0, node: Some(node) });
+                NodeId { index: self.slots.len() as u32 - 1, generation: 0 }
+            }
         }
     }
-}
-impl IndexMut<usize> for SceneNode {
-    fn index_mut(&mut self, index: usize) -> &mut SceneNode {
-        unsafe {
-            &mut (*self.children[index])
+
+    // Moves `node` into the scene as the last child of `parent`. Any parent or children the node
+    // was built with are ignored, children are added through the scene.
+    pub fn add_child(&mut self, parent: NodeId, mut node: SceneNode) -> NodeId {
+        assert!(self.contains(parent), "Cannot add a child to a node that is not in the scene");
+        node.parent = Some(parent);
+        node.children.clear();
+        let id = self.insert(node);
+        self.node_mut(parent).children.push(id);
+        self.mark_bounds_dirty(parent);
+        id
+    }
+
+    // Removes the node and its whole subtree, returning how many nodes were freed.
+    // Stale ids and the root are left alone.
+    #[allow(dead_code)]
+    pub fn remove(&mut self, id: NodeId) -> usize {
+        if id == self.root || !self.contains(id) {
+            return 0;
+        }
+        if let Some(parent) = self[id].parent {
+            self.node_mut(parent).children.retain(|&child| child != id);
+            self.mark_bounds_dirty(parent);
+        }
+
+        let mut removed = 0;
+        let mut pending = vec![id];
+        while let Some(current) = pending.pop() {
+            let slot = &mut self.slots[current.index as usize];
+            if let Some(node) = slot.node.take() {
+                pending.extend(node.children);
+                slot.generation = slot.generation.wrapping_add(1);
+                self.free.push(current.index);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&SceneNode> {
+        self.slots.get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    // Assumes the node will be changed, see the top of the file
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut SceneNode> {
+        if let Some(node) = self.get(id) {
+            node.transform_cache.local_dirty.set(true);
+            self.mark_world_dirty(id);
+        }
+        self.slots.get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+    }
+
+    // For changes to the hierarchy itself, which leave the node's own transform alone
+    fn node_mut(&mut self, id: NodeId) -> &mut SceneNode {
+        self.slots.get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+            .expect("Stale NodeId, the node was removed from the scene")
+    }
+
+    fn mark_world_dirty(&self, id: NodeId) {
+        let mut pending = vec![id];
+        while let Some(current) = pending.pop() {
+            if let Some(node) = self.get(current) {
+                // An already dirty node has an already dirty subtree, except for the node we started at.
+                // Bounds are only cleaned together with the world matrix, so the same goes for them.
+                node.transform_cache.bounds_dirty.set(true);
+                if node.transform_cache.world_dirty.replace(true) && current != id {
+                    continue;
+                }
+                pending.extend_from_slice(&node.children);
+            }
         }
+        if let Some(parent) = self.parent(id) {
+            self.mark_bounds_dirty(parent);
+        }
+    }
+
+    // Marks the bounds of the node and its ancestors dirty, stopping at the first one that already is
+    fn mark_bounds_dirty(&self, id: NodeId) {
+        let mut current = Some(id);
+        while let Some(node_id) = current {
+            let Some(node) = self.get(node_id) else { break };
+            if node.transform_cache.bounds_dirty.replace(true) {
+                break;
+            }
+            current = node.parent;
+        }
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.get(id).and_then(|node| node.parent)
+    }
+
+    #[allow(dead_code)]
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        self.get(id).map(|node| node.children()).unwrap_or(&[])
+    }
+
+    #[allow(dead_code)]
+    pub fn get_child(&self, id: NodeId, index: usize) -> Option<NodeId> {
+        self.children(id).get(index).copied()
+    }
+
+    // Takes the node's behaviours out for ticking them, which leaves its transform alone
+    pub fn take_behaviours(&mut self, id: NodeId) -> Option<Vec<Box<dyn Behaviour>>> {
+        self.get(id)?;
+        Some(std::mem::take(&mut self.node_mut(id).behaviours))
+    }
+
+    // Puts taken behaviours back in front of any attached since, unless the node was removed
+    pub fn return_behaviours(&mut self, id: NodeId, mut behaviours: Vec<Box<dyn Behaviour>>) {
+        if self.contains(id) {
+            let node = self.node_mut(id);
+            behaviours.append(&mut node.behaviours);
+            node.behaviours = behaviours;
+        }
+    }
+
+    // Where the node is among its parent's children
+    pub fn child_index(&self, id: NodeId) -> Option<usize> {
+        let parent = self.parent(id)?;
+        self.children(parent).iter().position(|&child| child == id)
+    }
+
+    // Moves the node to `index` among its siblings, or to the end if there are fewer
+    pub fn set_child_index(&mut self, id: NodeId, index: usize) {
+        let Some(parent) = self.parent(id) else { return };
+        let children = &mut self.node_mut(parent).children;
+        children.retain(|&child| child != id);
+        children.insert(index.min(children.len()), id);
+    }
+
+    // Adds a node that is not attached anywhere. It is not part of the drawn hierarchy until
+    // it is given a parent with `reparent`.
+    #[allow(dead_code)]
+    pub fn add_detached(&mut self, mut node: SceneNode) -> NodeId {
+        node.parent = None;
+        node.children.clear();
+        self.insert(node)
+    }
+
+    // Takes the node (and its subtree) out of the hierarchy without freeing it, so it can be
+    // reparented later. With `keep_world_transform` it stays where it was in the world, otherwise
+    // its local transform is kept and it is now relative to the world origin.
+    #[allow(dead_code)]
+    pub fn detach(&mut self, id: NodeId, keep_world_transform: bool) {
+        if id == self.root || !self.contains(id) {
+            return;
+        }
+        if keep_world_transform {
+            let world = self.world_matrix(id);
+            self[id].set_local_matrix(&world);
+        }
+        if let Some(parent) = self.node_mut(id).parent.take() {
+            self.node_mut(parent).children.retain(|&child| child != id);
+            self.mark_bounds_dirty(parent);
+        }
+        self.mark_world_dirty(id);
+    }
+
+    // Moves the node (and its subtree) to be the last child of `new_parent`. With
+    // `keep_world_transform` the node's local transform is recomputed against the new parent so it
+    // does not move in the world, e.g. when dropping cargo from a helicopter onto the terrain.
+    #[allow(dead_code)]
+    pub fn reparent(&mut self, id: NodeId, new_parent: NodeId, keep_world_transform: bool) {
+        assert!(id != self.root, "The root cannot be reparented");
+        assert!(self.contains(id) && self.contains(new_parent), "Cannot reparent nodes that are not in the scene");
+        assert!(!self.is_ancestor(id, new_parent), "Cannot reparent a node below itself");
+
+        let world = self.world_matrix(id);
+        self.detach(id, false);
+        if keep_world_transform {
+            let local = glm::inverse(&self.world_matrix(new_parent)) * world;
+            self[id].set_local_matrix(&local);
+        }
+        self.node_mut(id).parent = Some(new_parent);
+        self.node_mut(new_parent).children.push(id);
+        self.mark_world_dirty(id);
+    }
+
+    // Whether `ancestor` is `id` itself or above it in the hierarchy
+    pub fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut current = Some(id);
+        while let Some(node) = current {
+            if node == ancestor {
+                return true;
+            }
+            current = self.parent(node);
+        }
+        false
+    }
+
+    // The node's transform relative to its parent, cached
+    pub fn local_matrix(&self, id: NodeId) -> glm::Mat4 {
+        let node = &self[id];
+        let cache = &node.transform_cache;
+        if cache.local_dirty.get() {
+            cache.local.set(node.local_matrix());
+            cache.local_dirty.set(false);
+        }
+        cache.local.get()
+    }
+
+    // The node's transform relative to the world, i.e. all its ancestors' local transforms
+    // applied. Cached, only the dirty part of the path up to the root is recomputed.
+    pub fn world_matrix(&self, id: NodeId) -> glm::Mat4 {
+        // Walk up until a node with a valid world matrix (or the top of the tree)
+        let mut dirty_path = vec![];
+        let mut parent_world: glm::Mat4 = glm::identity();
+        let mut current = Some(id);
+        while let Some(node_id) = current {
+            let node = &self[node_id];
+            if !node.transform_cache.world_dirty.get() {
+                parent_world = node.transform_cache.world.get();
+                break;
+            }
+            dirty_path.push(node_id);
+            current = node.parent;
+        }
+
+        // And back down again, refreshing the caches on the way
+        for &node_id in dirty_path.iter().rev() {
+            parent_world *= self.local_matrix(node_id);
+            let cache = &self[node_id].transform_cache;
+            cache.world.set(parent_world);
+            cache.world_dirty.set(false);
+        }
+        parent_world
+    }
+
+    // The box around the meshes of the node and everything below it, in world space. Empty if none
+    // of them has a mesh. Cached, only subtrees with changes are recomputed.
+    pub fn subtree_bounds(&self, id: NodeId) -> Aabb {
+        self.update_bounds(id);
+        self[id].transform_cache.bounds.get()
+    }
+
+    // How many nodes with a mesh there are in the subtree, the node included
+    pub fn subtree_mesh_count(&self, id: NodeId) -> usize {
+        self.update_bounds(id);
+        self[id].transform_cache.mesh_count.get()
+    }
+
+    fn update_bounds(&self, id: NodeId) {
+        let node = &self[id];
+        let cache = &node.transform_cache;
+        if !cache.bounds_dirty.get() {
+            return;
+        }
+        let mut bounds = Aabb::empty();
+        let mut mesh_count = 0;
+        if let Some(mesh) = node.primary_mesh() {
+            bounds = mesh.bounds.transformed(&self.world_matrix(id));
+            mesh_count = 1;
+        }
+        for &child in &node.children {
+            self.update_bounds(child);
+            let child_cache = &self[child].transform_cache;
+            bounds = bounds.merge(&child_cache.bounds.get());
+            mesh_count += child_cache.mesh_count.get();
+        }
+        cache.bounds.set(bounds);
+        cache.mesh_count.set(mesh_count);
+        cache.bounds_dirty.set(false);
+    }
+
+    // Whether the node is visible and so are all its ancestors, i.e. whether it gets drawn
+    pub fn is_visible(&self, id: NodeId) -> bool {
+        let mut current = Some(id);
+        while let Some(node) = current {
+            if !self[node].visible {
+                return false;
+            }
+            current = self.parent(node);
+        }
+        true
+    }
+
+    // Number of live nodes, including the root
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+}
+
+
+// You can also use square brackets to access the nodes of a Scene, which panics on stale ids
+impl Index<NodeId> for Scene {
+    type Output = SceneNode;
+    fn index(&self, id: NodeId) -> &SceneNode {
+        self.get(id).expect("Stale NodeId, the node was removed from the scene")
+    }
+}
+impl IndexMut<NodeId> for Scene {
+    fn index_mut(&mut self, id: NodeId) -> &mut SceneNode {
+        self.get_mut(id).expect("Stale NodeId, the node was removed from the scene")
     }
 }
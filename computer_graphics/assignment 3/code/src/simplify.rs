@@ -0,0 +1,68 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+
+use crate::geometry::Aabb;
+use crate::mesh::Mesh;
+
+// Cheaper versions of a mesh for drawing it far away (see `lod`), by vertex clustering: the mesh's
+// bounds are cut into a grid of cells, every vertex in a cell is merged into one at their average
+// position, color and normal, and the triangles that collapse in the process are dropped.
+//
+// It is fast and never fails, but does not care about the shape: thin parts can disappear and
+// details smaller than a cell are gone. Good enough for things too far away to tell.
+
+// `resolution` is the number of cells along the longest side of the bounds
+pub fn vertex_clustering(mesh: &Mesh, resolution: u32) -> Mesh {
+    let mut bounds = Aabb::empty();
+    for vertex in 0..mesh.vertex_count() {
+        bounds.grow(&mesh.position(vertex as u32));
+    }
+    if bounds.is_empty() {
+        return Mesh::from_arrays(vec![], vec![], vec![], [1.0, 1.0, 1.0, 1.0]);
+    }
+    let cell_size = glm::comp_max(&bounds.extent()).max(f32::EPSILON) / resolution.max(1) as f32;
+
+    let normals = mesh.vertex_normals();
+    let mut cells: HashMap<[i32; 3], u32> = HashMap::new();
+    let mut sums: Vec<(glm::Vec3, glm::Vec3, glm::Vec4, f32)> = vec![]; // position, normal, color, count
+    let cluster: Vec<u32> = (0..mesh.vertex_count())
+        .map(|vertex| {
+            let position = mesh.position(vertex as u32);
+            let cell = (position - bounds.min) / cell_size;
+            let key = [cell.x as i32, cell.y as i32, cell.z as i32];
+            let index = *cells.entry(key).or_insert_with(|| {
+                sums.push((glm::zero(), glm::zero(), glm::zero(), 0.0));
+                sums.len() as u32 - 1
+            });
+            let color = &mesh.colors[vertex * 4..vertex * 4 + 4];
+            let sum = &mut sums[index as usize];
+            sum.0 += position;
+            sum.1 += normals[vertex];
+            sum.2 += glm::vec4(color[0], color[1], color[2], color[3]);
+            sum.3 += 1.0;
+            index
+        })
+        .collect();
+
+    let mut vertices = Vec::with_capacity(sums.len() * 3);
+    let mut merged_normals = Vec::with_capacity(sums.len() * 3);
+    let mut colors = Vec::with_capacity(sums.len() * 4);
+    for (position, normal, color, count) in &sums {
+        vertices.extend_from_slice((position / *count).as_slice());
+        let normal = if glm::length(normal) > 0.0 { glm::normalize(normal) } else { *normal };
+        merged_normals.extend_from_slice(normal.as_slice());
+        colors.extend_from_slice((color / *count).as_slice());
+    }
+
+    // Triangles with two corners in the same cell have collapsed into a line or a point
+    let indices: Vec<u32> = mesh.indices.chunks_exact(3)
+        .map(|triangle| [cluster[triangle[0] as usize], cluster[triangle[1] as usize], cluster[triangle[2] as usize]])
+        .filter(|[a, b, c]| a != b && b != c && c != a)
+        .flatten()
+        .collect();
+
+    let mut simplified = Mesh::from_arrays(vertices, merged_normals, indices, [1.0, 1.0, 1.0, 1.0]);
+    simplified.colors = colors;
+    simplified
+}
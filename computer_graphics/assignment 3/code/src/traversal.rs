@@ -0,0 +1,121 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::VecDeque;
+
+use crate::scene_graph::{NodeId, Scene, SceneNode};
+
+// Ways of walking a scene graph without writing yet another recursive function: depth first
+// iterators in pre- and post-order, a breadth first iterator, and `Scene::walk` which drives a
+// `SceneVisitor` through enter/leave hooks and lets it skip subtrees. Every node comes with its
+// world matrix (from the scene's cache) and its depth below the node the traversal started at.
+
+#[allow(dead_code)]
+pub struct Visit<'a> {
+    pub id           : NodeId,
+    pub node         : &'a SceneNode,
+    pub world_matrix : glm::Mat4,
+    pub depth        : usize,
+}
+
+pub trait SceneVisitor {
+    // Called before the node's children. Returning false skips them, e.g. for culled subtrees.
+    fn enter(&mut self, _scene: &Scene, _visit: &Visit) -> bool {
+        true
+    }
+
+    // Called after the node's children, also when they were skipped
+    fn leave(&mut self, _scene: &Scene, _visit: &Visit) {}
+}
+
+fn visit(scene: &Scene, id: NodeId, depth: usize) -> Visit<'_> {
+    Visit {
+        id,
+        node: &scene[id],
+        world_matrix: scene.world_matrix(id),
+        depth,
+    }
+}
+
+// Parents before their children, children in order
+pub struct DepthFirst<'a> {
+    scene : &'a Scene,
+    stack : Vec<(NodeId, usize)>,
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = Visit<'a>;
+    fn next(&mut self) -> Option<Visit<'a>> {
+        let (id, depth) = self.stack.pop()?;
+        self.stack.extend(self.scene[id].children().iter().rev().map(|&child| (child, depth + 1)));
+        Some(visit(self.scene, id, depth))
+    }
+}
+
+// Children before their parents, children in order
+pub struct DepthFirstPostOrder<'a> {
+    scene : &'a Scene,
+    stack : Vec<(NodeId, usize, bool)>,  // The flag is set once the children have been pushed
+}
+
+impl<'a> Iterator for DepthFirstPostOrder<'a> {
+    type Item = Visit<'a>;
+    fn next(&mut self) -> Option<Visit<'a>> {
+        loop {
+            let (id, depth, expanded) = self.stack.pop()?;
+            if expanded {
+                return Some(visit(self.scene, id, depth));
+            }
+            self.stack.push((id, depth, true));
+            self.stack.extend(self.scene[id].children().iter().rev().map(|&child| (child, depth + 1, false)));
+        }
+    }
+}
+
+// Level by level
+pub struct BreadthFirst<'a> {
+    scene : &'a Scene,
+    queue : VecDeque<(NodeId, usize)>,
+}
+
+impl<'a> Iterator for BreadthFirst<'a> {
+    type Item = Visit<'a>;
+    fn next(&mut self) -> Option<Visit<'a>> {
+        let (id, depth) = self.queue.pop_front()?;
+        self.queue.extend(self.scene[id].children().iter().map(|&child| (child, depth + 1)));
+        Some(visit(self.scene, id, depth))
+    }
+}
+
+impl Scene {
+    #[allow(dead_code)]
+    pub fn depth_first(&self, start: NodeId) -> DepthFirst<'_> {
+        DepthFirst { scene: self, stack: self.get(start).map(|_| (start, 0)).into_iter().collect() }
+    }
+
+    #[allow(dead_code)]
+    pub fn depth_first_post_order(&self, start: NodeId) -> DepthFirstPostOrder<'_> {
+        DepthFirstPostOrder { scene: self, stack: self.get(start).map(|_| (start, 0, false)).into_iter().collect() }
+    }
+
+    #[allow(dead_code)]
+    pub fn breadth_first(&self, start: NodeId) -> BreadthFirst<'_> {
+        BreadthFirst { scene: self, queue: self.get(start).map(|_| (start, 0)).into_iter().collect() }
+    }
+
+    // Runs the visitor over the subtree below `start`, depth first
+    pub fn walk<V: SceneVisitor + ?Sized>(&self, start: NodeId, visitor: &mut V) {
+        if self.contains(start) {
+            self.walk_node(start, 0, visitor);
+        }
+    }
+
+    fn walk_node<V: SceneVisitor + ?Sized>(&self, id: NodeId, depth: usize, visitor: &mut V) {
+        let current = visit(self, id, depth);
+        if visitor.enter(self, &current) {
+            for &child in current.node.children() {
+                self.walk_node(child, depth + 1, visitor);
+            }
+        }
+        visitor.leave(self, &current);
+    }
+}